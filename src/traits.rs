@@ -1,16 +1,77 @@
-use crate::types::{Account, AccountId, AccountType, Error, Hash, PK};
+use crate::types::{Account, AccountId, AccountType, BlockTiming, DecodeError, Hash, PK, StateError, Target};
+use std::fmt::Debug;
 
 pub trait Hashable {
     fn hash(&self) -> Hash;
 }
 
+/// Canonical binary encoding, the format is described in `src/types/encoding.rs`.
+pub trait Encode {
+    fn encode_to(&self, out: &mut Vec<u8>);
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_to(&mut out);
+
+        out
+    }
+}
+
+pub trait Decode: Sized {
+    /// Reads a value from the front of `input` and advances `input` past it.
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError>;
+
+    /// Decodes a value which takes up all of `bytes`.
+    fn decode(mut bytes: &[u8]) -> Result<Self, DecodeError> {
+        let value = Self::decode_from(&mut bytes)?;
+        if !bytes.is_empty() {
+            return Err(DecodeError::TrailingBytes(bytes.len()));
+        }
+
+        Ok(value)
+    }
+}
+
+/// Accounts a transaction is executed against. Backends can keep them in memory, on disk or
+/// on top of another state.
 pub trait WorldState {
     fn create_account(
         &mut self,
         account_id: AccountId,
         account_type: AccountType,
         public_key: PK,
-    ) -> Result<(), Error>;
-    fn get_account_by_id(&self, account_id: AccountId) -> Option<&Account>;
-    fn get_account_by_id_mut(&mut self, account_id: AccountId) -> Option<&mut Account>;
+    ) -> Result<(), StateError>;
+    fn get_account_by_id(&self, account_id: &str) -> Option<&Account>;
+    fn get_account_by_id_mut(&mut self, account_id: &str) -> Option<&mut Account>;
+    fn remove_account(&mut self, account_id: &str) -> Option<Account>;
+
+    /// Every account, in no particular order.
+    fn accounts(&self) -> Box<dyn Iterator<Item = (&AccountId, &Account)> + '_>;
+
+    fn contains_account(&self, account_id: &str) -> bool {
+        self.get_account_by_id(account_id).is_some()
+    }
+
+    /// Nonce the next transaction sent from the account has to carry.
+    fn get_next_nonce(&self, account_id: &str) -> Option<u128> {
+        self.get_account_by_id(account_id).map(|account| account.nonce)
+    }
+
+    /// Starts a batch of changes, e.g. the ones of a transaction. Batches can be nested.
+    fn begin_batch(&mut self);
+
+    /// Keeps the changes of the innermost batch.
+    fn commit_batch(&mut self) -> Result<(), StateError>;
+
+    /// Undoes the changes of the innermost batch.
+    fn revert_batch(&mut self);
+}
+
+pub trait DifficultyAlgorithm: Debug + Send + Sync {
+    /// Number of most recent blocks `next_target` needs to look at.
+    fn window(&self) -> usize;
+
+    /// Target for the block at `height`. `history` holds up to `window()` of its ancestors,
+    /// oldest first and ending with the parent, and is empty for the genesis block.
+    fn next_target(&self, height: u64, history: &[BlockTiming]) -> Target;
 }
@@ -1,17 +1,21 @@
-use crate::types::{Balance, PK};
+use crate::traits::{Decode, Encode};
+use crate::types::encoding::decode_tag;
+use crate::types::{Balance, DecodeError, PK};
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum AccountType {
     User,
     Contract,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Account {
-    account_type: AccountType,
+    pub account_type: AccountType,
     pub balance: Balance,
     pub public_key : PK,
+    // number of transactions sent from the account
+    pub nonce: u128,
 }
 
 impl Account {
@@ -20,7 +24,46 @@ impl Account {
             account_type,
             balance: 0,
             public_key,
+            nonce: 0,
         }
     }
 }
 
+impl Encode for AccountType {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            AccountType::User => 0,
+            AccountType::Contract => 1,
+        };
+        tag.encode_to(out);
+    }
+}
+
+impl Decode for AccountType {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match decode_tag(input, 2)? {
+            0 => Ok(AccountType::User),
+            _ => Ok(AccountType::Contract),
+        }
+    }
+}
+
+impl Encode for Account {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.account_type.encode_to(out);
+        self.balance.encode_to(out);
+        self.public_key.encode_to(out);
+        self.nonce.encode_to(out);
+    }
+}
+
+impl Decode for Account {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            account_type: Decode::decode_from(input)?,
+            balance: Decode::decode_from(input)?,
+            public_key: Decode::decode_from(input)?,
+            nonce: Decode::decode_from(input)?,
+        })
+    }
+}
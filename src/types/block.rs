@@ -1,42 +1,114 @@
-use crate::traits::Hashable;
-use crate::types::{Hash, Timestamp, Transaction};
-use blake2::digest::FixedOutput;
-use blake2::{Blake2s, Digest};
+use crate::traits::{Decode, Encode, Hashable};
+use crate::types::{AccountId, BlockError, BlockHeader, DecodeError, Hash, MAX_BLOCK_SIZE, MAX_BLOCK_TRANSACTIONS, MerkleProof,
+                   MerkleTree, Timestamp, Transaction, TransactionData};
 use crate::utils::generate_timestamp;
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct Block {
-    nonce: u128,
-    pub(crate) timestamp: Timestamp,
+    pub header: BlockHeader,
     pub hash: Option<Hash>,
-    pub(crate) prev_hash: Option<Hash>,
     pub transactions: Vec<Transaction>,
 }
 
+/// Most a block can hold: its encoded size in bytes and its transactions, the coinbase included.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockLimits {
+    pub max_size: usize,
+    pub max_transactions: usize,
+}
+
+impl Default for BlockLimits {
+    fn default() -> Self {
+        BlockLimits::new(MAX_BLOCK_SIZE, MAX_BLOCK_TRANSACTIONS)
+    }
+}
+
+impl BlockLimits {
+    pub fn new(max_size: usize, max_transactions: usize) -> Self {
+        Self { max_size, max_transactions }
+    }
+
+    pub fn check(&self, block: &Block) -> Result<(), BlockError> {
+        if block.transactions.len() > self.max_transactions {
+            return Err(BlockError::TooManyTransactions {
+                count: block.transactions.len(),
+                limit: self.max_transactions,
+            });
+        }
+
+        let size = block.encode().len();
+        if size > self.max_size {
+            return Err(BlockError::TooLarge { size, limit: self.max_size });
+        }
+
+        Ok(())
+    }
+}
+
 impl Block {
     pub fn new(prev_hash: Option<Hash>) -> Self {
         let mut block = Block {
-            prev_hash,
+            header: BlockHeader {
+                prev_hash,
+                timestamp: generate_timestamp(),
+                ..Default::default()
+            },
             ..Default::default()
         };
-        block.timestamp = generate_timestamp();
-        block.update_hash();
+        block.update_merkle_root();
 
         block
     }
 
     pub fn set_nonce(&mut self, nonce: u128) {
-        self.nonce = nonce;
+        self.header.nonce = nonce;
+        self.update_hash();
+    }
+
+    pub fn set_timestamp(&mut self, timestamp: Timestamp) {
+        self.header.timestamp = timestamp;
         self.update_hash();
     }
 
+    pub fn timestamp(&self) -> Timestamp {
+        self.header.timestamp
+    }
+
     pub fn add_transaction(&mut self, transaction: Transaction) {
         self.transactions.push(transaction);
-        self.update_hash();
+        self.update_merkle_root();
     }
 
+    pub fn set_transactions(&mut self, transactions: Vec<Transaction>) {
+        self.transactions = transactions;
+        self.update_merkle_root();
+    }
+
+    /// Account the coinbase pays, None for a genesis block. Transaction fees go there too.
+    pub fn miner(&self) -> Option<&AccountId> {
+        match self.transactions.first().map(|tx| &tx.data) {
+            Some(TransactionData::Coinbase { to, .. }) => Some(to),
+            _ => None,
+        }
+    }
+
+    /// Proof that the transaction with `tx_hash` is committed to by `header.merkle_root`. The
+    /// proof is for the leaf `Transaction::full_hash`.
+    pub fn merkle_proof(&self, tx_hash: &Hash) -> Option<MerkleProof> {
+        let index = self.transactions.iter().position(|tx| &tx.hash() == tx_hash)?;
+        MerkleTree::from_transactions(&self.transactions).proof(index)
+    }
+
+    /// Checks that the header commits to the transactions and the hash to the header.
     pub fn verify(&self) -> bool {
-        matches!(&self.hash, Some(hash) if hash == &self.hash())
+        self.header.check_hashes().is_ok()
+            && self.header.merkle_root == MerkleTree::from_transactions(&self.transactions).root()
+            && matches!(&self.hash, Some(hash) if hash == &self.hash())
+    }
+
+    fn update_merkle_root(&mut self) {
+        self.header.merkle_root = MerkleTree::from_transactions(&self.transactions).root();
+        self.update_hash();
     }
 
     fn update_hash(&mut self) {
@@ -46,12 +118,27 @@ impl Block {
 
 impl Hashable for Block {
     fn hash(&self) -> Hash {
-        let mut hasher = Blake2s::new();
-        hasher.update(format!("{:?}", (self.prev_hash.clone(), self.nonce)).as_bytes());
-        for tx in self.transactions.iter() {
-            hasher.update(tx.hash())
-        }
+        self.header.hash()
+    }
+}
+
+// The hash is derived from the header, so it isn't part of the encoding
+impl Encode for Block {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.header.encode_to(out);
+        self.transactions.encode_to(out);
+    }
+}
+
+impl Decode for Block {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let mut block = Block {
+            header: Decode::decode_from(input)?,
+            hash: None,
+            transactions: Decode::decode_from(input)?,
+        };
+        block.update_hash();
 
-        hex::encode(hasher.finalize_fixed())
+        Ok(block)
     }
 }
@@ -1,18 +1,64 @@
-use crate::traits::{Hashable, WorldState};
-use crate::types::{Account, AccountId, AccountType, Block, Chain, COEFFICIENT_LENGTH, Error,
-                   EXPECTED_TIME, Hash, MAX_COMPACT_FORM, MAX_TARGET, PK, Target, Timestamp, Transaction};
-use std::collections::hash_map::Entry;
+use crate::traits::{DifficultyAlgorithm, Encode, Hashable, WorldState};
+use crate::types::{Account, AccountId, AccountType, Balance, Block, BlockError, BlockLimits, BlockTiming, Chain, ChainId, ExecutionContext, FeeMarket, Hash, InvalidBlock,
+                   HEADER_SIZE, MAX_FUTURE_DRIFT, MAX_ORPHAN_BLOCKS, MAX_SUPPLY, MEDIAN_TIME_SPAN, MemoryState, Mempool, MempoolError, ORPHAN_EXPIRY, PerBlock, PK, RewardSchedule,
+                   StateError, StateOverlay, Target, Timestamp, Transaction, TransactionData, TxError, Work};
+use crate::types::undo::BlockUndo;
 use std::collections::{HashMap};
-use crate::utils::check_target;
+use std::sync::Arc;
+use crate::utils::generate_timestamp;
+
+// Everything the fork choice needs to know about a block, whether it is on the main chain or not
+#[derive(Debug, Clone)]
+struct BlockMeta {
+    height: u64,
+    prev_hash: Option<Hash>,
+    total_work: Work,
+    target: Target,
+    timestamp: Timestamp,
+    base_fee: Balance,
+    // encoded size of the transactions paying the base fee
+    size: usize,
+}
+
+// Shared, so that a replay of the chain retargets with the same algorithm
+#[derive(Debug, Clone)]
+struct Difficulty(Arc<dyn DifficultyAlgorithm>);
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Difficulty(Arc::new(PerBlock::default()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockStatus {
+    Appended,
+    // held until its parent arrives
+    Orphan,
+}
 
 #[derive(Default, Debug)]
 pub struct Blockchain {
     pub blocks: Chain<Block>,
-    accounts: HashMap<AccountId, Account>,
-    transaction_pool: Vec<Transaction>,
-    last_timestamp: Timestamp,
+    // what each main chain block changed, in chain order
+    undo_log: Vec<BlockUndo>,
+    side_blocks: HashMap<Hash, Block>,
+    // blocks with an unknown parent and the time they arrived, by parent hash
+    orphans: HashMap<Hash, Vec<(Timestamp, Block)>>,
+    block_index: HashMap<Hash, BlockMeta>,
+    accounts: MemoryState,
+    transaction_pool: Mempool,
+    difficulty: Difficulty,
     pub(crate) current_target: Target,
-    pub(crate) compact_form: String,
+    rewards: RewardSchedule,
+    fee_market: FeeMarket,
+    limits: BlockLimits,
+    configured_chain_id: Option<ChainId>,
+    // derived from the genesis hash, unless configured
+    genesis_chain_id: Option<ChainId>,
+    // coins on the main chain's accounts: everything minted and claimed by coinbases, minus burned fees
+    total_supply: Balance,
+    supply_cap: Balance,
 }
 
 impl WorldState for Blockchain {
@@ -21,22 +67,36 @@ impl WorldState for Blockchain {
         account_id: AccountId,
         account_type: AccountType,
         public_key: PK,
-    ) -> Result<(), Error> {
-        match self.accounts.entry(account_id.clone()) {
-            Entry::Occupied(_) => Err(format!("AccountId already exist: {}", account_id)),
-            Entry::Vacant(v) => {
-                v.insert(Account::new(account_type, public_key));
-                Ok(())
-            }
-        }
+    ) -> Result<(), StateError> {
+        self.accounts.create_account(account_id, account_type, public_key)
+    }
+
+    fn get_account_by_id(&self, account_id: &str) -> Option<&Account> {
+        self.accounts.get_account_by_id(account_id)
+    }
+
+    fn get_account_by_id_mut(&mut self, account_id: &str) -> Option<&mut Account> {
+        self.accounts.get_account_by_id_mut(account_id)
     }
 
-    fn get_account_by_id(&self, account_id: AccountId) -> Option<&Account> {
-        self.accounts.get(&account_id)
+    fn remove_account(&mut self, account_id: &str) -> Option<Account> {
+        self.accounts.remove_account(account_id)
     }
 
-    fn get_account_by_id_mut(&mut self, account_id: AccountId) -> Option<&mut Account> {
-        self.accounts.get_mut(&account_id)
+    fn accounts(&self) -> Box<dyn Iterator<Item = (&AccountId, &Account)> + '_> {
+        self.accounts.accounts()
+    }
+
+    fn begin_batch(&mut self) {
+        self.accounts.begin_batch();
+    }
+
+    fn commit_batch(&mut self) -> Result<(), StateError> {
+        self.accounts.commit_batch()
+    }
+
+    fn revert_batch(&mut self) {
+        self.accounts.revert_batch();
     }
 }
 
@@ -45,136 +105,725 @@ impl Blockchain {
         let mut bc = Blockchain {
             ..Default::default()
         };
-        bc.current_target = MAX_TARGET;
-        bc.compact_form = MAX_COMPACT_FORM.to_string();
+        bc.current_target = Target::MAX;
+        bc.supply_cap = MAX_SUPPLY;
 
         bc
     }
 
+    /// Creates a blockchain which retargets with the given difficulty algorithm.
+    pub fn with_difficulty<D: DifficultyAlgorithm + 'static>(difficulty: D) -> Self {
+        let mut bc = Blockchain::new();
+        bc.difficulty = Difficulty(Arc::new(difficulty));
+        bc.update_head_state();
+
+        bc
+    }
+
+    /// Sets the chain ID transactions are signed for, instead of deriving it from the genesis block.
+    pub fn with_chain_id(mut self, chain_id: ChainId) -> Self {
+        self.configured_chain_id = Some(chain_id);
+
+        self
+    }
+
+    /// Sets the subsidy a coinbase can claim.
+    pub fn with_reward_schedule(mut self, rewards: RewardSchedule) -> Self {
+        self.rewards = rewards;
+
+        self
+    }
+
+    /// Sets how the base fee follows block fullness.
+    pub fn with_fee_market(mut self, fee_market: FeeMarket) -> Self {
+        self.fee_market = fee_market;
+
+        self
+    }
+
+    /// Sets how large blocks can be.
+    pub fn with_block_limits(mut self, limits: BlockLimits) -> Self {
+        self.limits = limits;
+
+        self
+    }
+
+    /// Sets the most coins there can ever be, blocks which would create more are rejected.
+    pub fn with_supply_cap(mut self, supply_cap: Balance) -> Self {
+        self.supply_cap = supply_cap;
+
+        self
+    }
+
+    /// Sets how many transactions the pool can hold.
+    pub fn with_mempool_capacity(mut self, capacity: usize) -> Self {
+        self.transaction_pool = Mempool::new(capacity);
+
+        self
+    }
+
+    /// ID of the chain transaction signatures have to be made for: the configured one, or the
+    /// last 8 bytes of the genesis hash. Until the genesis block of a chain without a configured
+    /// ID is appended, which includes the transactions of the genesis block itself, it is 0.
+    pub fn chain_id(&self) -> ChainId {
+        self.configured_chain_id.or(self.genesis_chain_id).unwrap_or(0)
+    }
+
     pub fn len(&self) -> usize {
         self.blocks.len()
     }
 
-    pub fn append_block(&mut self, block: Block) -> Result<(), Error> {
+    /// Coins held by all accounts at the head of the main chain.
+    pub fn total_supply(&self) -> Balance {
+        self.total_supply
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Appends a block to the block tree.
+    ///
+    /// A block on top of the current head extends the main chain. A block on top of any other
+    /// known block is kept as a side branch, and the chain reorganizes to it as soon as the
+    /// branch has more cumulative work than the main chain. A block whose parent is unknown, or
+    /// a second block without a parent, is rejected; see `receive_block` for out of order blocks.
+    pub fn append_block(&mut self, block: Block) -> Result<(), BlockError> {
+        self.append_block_at(block, generate_timestamp())
+    }
+
+    /// Same as `append_block`, with `now` as the current time for the timestamp rules.
+    pub fn append_block_at(&mut self, block: Block, now: Timestamp) -> Result<(), BlockError> {
+        let hash = block.hash.clone();
+        self.connect_block(block, now)?;
+        self.connect_orphans(hash.unwrap(), now);
+        self.prune_mempool();
+
+        Ok(())
+    }
+
+    /// Adds a transaction to the pool, if it is valid on top of the current head. Its nonce can
+    /// be ahead of the sender's, it is then held until the transactions in between arrive.
+    pub fn submit_transaction(&mut self, tx: Transaction) -> Result<(), MempoolError> {
+        let context = self.execution_context(None);
+        let mut pool = std::mem::take(&mut self.transaction_pool);
+        let res = pool.insert(tx, self, &context);
+        self.transaction_pool = pool;
+
+        res
+    }
+
+    /// Pooled transactions which can be executed on top of the current head, in inclusion order.
+    pub fn pending_transactions(&self) -> Vec<&Transaction> {
+        self.transaction_pool.pending(self)
+    }
+
+    pub fn mempool_len(&self) -> usize {
+        self.transaction_pool.len()
+    }
+
+    /// Builds a block on top of the current head, ready to be mined: a coinbase paying the full
+    /// subsidy to `miner`, who also gets the fees, then pooled transactions in pool order, skipping the ones which fail
+    /// to execute or don't fit in the block limits.
+    pub fn build_block(&self, miner: &AccountId) -> Result<Block, TxError> {
+        let prev_hash = self.get_last_block_hash();
+        let mut block = Block::new(prev_hash.clone());
+        block.header.height = self.next_height(&prev_hash).unwrap();
+        block.header.bits = self.current_target.to_compact();
+        block.header.base_fee = self.next_base_fee(&prev_hash).unwrap();
+        if let Some(median) = self.median_time_past(&prev_hash) {
+            block.header.timestamp = block.header.timestamp.max(median);
+        }
+
+        let context = self.execution_context(Some(miner.clone()));
+        let mut state = StateOverlay::new(&self.accounts);
+        let coinbase = Transaction::new(TransactionData::Coinbase {
+            to: miner.clone(),
+            amount: context.subsidy.min(self.supply_cap.saturating_sub(self.total_supply)),
+            height: context.height,
+        }, None);
+        coinbase.execute(&mut state, &context)?;
+
+        // header, transaction count and transactions
+        let mut size = HEADER_SIZE + 4 + coinbase.encode().len();
+        let mut transactions = vec![coinbase];
+        for tx in self.pending_transactions() {
+            if transactions.len() >= self.limits.max_transactions {
+                break;
+            }
+            let tx_size = tx.encode().len();
+            if size + tx_size > self.limits.max_size {
+                continue;
+            }
+
+            // a failed transaction reverts its own changes
+            if tx.execute(&mut state, &context).is_ok() {
+                transactions.push(tx.clone());
+                size += tx_size;
+            }
+        }
+
+        block.set_transactions(transactions);
+
+        Ok(block)
+    }
+
+    /// Entry point for blocks which may arrive out of order. A block whose parent is unknown
+    /// is held back, and appended along with its held descendants once the parent arrives.
+    /// Held blocks expire after `ORPHAN_EXPIRY` seconds, and the oldest one makes room for a
+    /// new one once `MAX_ORPHAN_BLOCKS` are held.
+    pub fn receive_block(&mut self, block: Block) -> Result<BlockStatus, BlockError> {
+        self.receive_block_at(block, generate_timestamp())
+    }
+
+    /// Same as `receive_block`, with `now` as the current time.
+    pub fn receive_block_at(&mut self, block: Block, now: Timestamp) -> Result<BlockStatus, BlockError> {
+        match &block.header.prev_hash {
+            Some(prev_hash) if !self.block_index.contains_key(prev_hash) => {
+                self.add_orphan(block, now)?;
+                Ok(BlockStatus::Orphan)
+            }
+            _ => {
+                self.append_block_at(block, now)?;
+                Ok(BlockStatus::Appended)
+            }
+        }
+    }
+
+    /// Number of blocks waiting for their parent.
+    pub fn orphans_len(&self) -> usize {
+        self.orphans.values().map(Vec::len).sum()
+    }
+
+    /// Takes the head block off the main chain and restores the state from before it. The
+    /// block is forgotten along with the side branches built on it, so it can be appended again,
+    /// and its transactions go back to the pool.
+    pub fn pop_block(&mut self) -> Option<Block> {
+        let block = self.disconnect_head()?;
+        self.forget(block.hash.clone().unwrap());
+        if self.is_empty() {
+            self.genesis_chain_id = None;
+        }
+        self.update_head_state();
+        self.resubmit_transactions(std::slice::from_ref(&block));
+
+        Some(block)
+    }
+
+    /// Pops blocks until the head is at `height`, returns the popped blocks, head first.
+    pub fn rewind_to(&mut self, height: u64) -> Vec<Block> {
+        let mut popped = Vec::new();
+        while self.blocks.len() as u64 > height.saturating_add(1) {
+            popped.extend(self.pop_block());
+        }
+
+        popped
+    }
+
+    fn connect_block(&mut self, block: Block, now: Timestamp) -> Result<(), BlockError> {
+        match &block.header.prev_hash {
+            None if !self.is_empty() => {
+                return Err(BlockError::MissingPrevHash);
+            }
+            Some(prev_hash) if !self.block_index.contains_key(prev_hash) => {
+                return Err(BlockError::UnknownParent(prev_hash.clone()));
+            }
+            _ => {}
+        }
+
         if !block.verify() {
-            return Err("Block has invalid hash".to_string());
+            return Err(BlockError::InvalidHash);
         }
-        if check_target(self.current_target.clone(), block.hash.clone().unwrap()) == false {
-            return Err("Block hash > current target!".to_string());
+        let hash = block.hash.clone().unwrap();
+        if self.block_index.contains_key(&hash) {
+            return Err(BlockError::AlreadyExists);
         }
-        let is_genesis = self.blocks.len() == 0;
 
-        if block.transactions.len() == 0 {
-            return Err("Block has 0 transactions.".to_string());
+        // the parent is known at this point
+        let height = self.next_height(&block.header.prev_hash).unwrap();
+        let target = self.next_target(&block.header.prev_hash).unwrap();
+
+        if block.header.height != height {
+            return Err(BlockError::InvalidHeight { height: block.header.height, expected: height });
         }
 
-        let account_backup = self.accounts.clone();
-        for tx in &block.transactions {
-            let res = tx.execute(self, is_genesis);
-            if let Err(error) = res {
-                self.accounts = account_backup;
-                return Err(format!("Error during tx execution: {}", error));
+        if block.header.bits != target.to_compact() {
+            return Err(BlockError::InvalidBits(block.header.bits));
+        }
+
+        let base_fee = self.next_base_fee(&block.header.prev_hash).unwrap();
+        if block.header.base_fee != base_fee {
+            return Err(BlockError::InvalidBaseFee { base_fee: block.header.base_fee, expected: base_fee });
+        }
+
+        if !target.is_met_by(&hash) {
+            return Err(BlockError::InsufficientWork);
+        }
+
+        if block.transactions.is_empty() {
+            return Err(BlockError::NoTransactions);
+        }
+
+        self.limits.check(&block)?;
+
+        if block.header.prev_hash.is_some() && !block.transactions[0].is_coinbase() {
+            return Err(BlockError::MissingCoinbase);
+        }
+        if let Some(index) = block.transactions.iter().skip(1).position(Transaction::is_coinbase) {
+            return Err(BlockError::MisplacedCoinbase { index: index + 1 });
+        }
+
+        self.check_timestamp(&block, now)?;
+
+        let parent = block.header.prev_hash.as_ref().and_then(|prev_hash| self.block_index.get(prev_hash));
+        let meta = BlockMeta {
+            height,
+            prev_hash: block.header.prev_hash.clone(),
+            total_work: parent.map_or(Work::ZERO, |parent| parent.total_work).saturating_add(target.work()),
+            target,
+            timestamp: block.header.timestamp,
+            base_fee,
+            size: block.transactions.iter().filter(|tx| tx.pays_base_fee()).map(|tx| tx.encode().len()).sum(),
+        };
+
+        if block.header.prev_hash == self.get_last_block_hash() {
+            self.connect_head(block)?;
+            if self.blocks.len() == 1 {
+                self.genesis_chain_id = Some(chain_id_from_hash(&hash));
             }
+            self.block_index.insert(hash, meta);
+            self.update_head_state();
+            return Ok(());
         }
 
-        if !is_genesis {
-            Blockchain::target_adjust(self, block.timestamp.clone());
+        let total_work = meta.total_work;
+        self.block_index.insert(hash.clone(), meta);
+        self.side_blocks.insert(hash.clone(), block);
+
+        if total_work > self.total_work() {
+            return self.reorganize(hash);
         }
 
-        self.last_timestamp = block.timestamp;
-        self.blocks.append(block);
         Ok(())
     }
 
-    pub fn validate(&self) -> Result<(), Error> {
+    /// Checks the hashes and the linkage of the main chain.
+    pub fn validate(&self) -> Result<(), InvalidBlock> {
         let mut block_num = self.blocks.len();
         let mut prev_block_hash: Option<Hash> = None;
 
         for block in self.blocks.iter() {
             let is_genesis = block_num == 1;
+            let invalid = |height, reason| Err(InvalidBlock { height, reason });
 
             if !block.verify() {
-                return Err(format!("Block {} has invalid hash", block_num));
+                return invalid(block_num as u64 - 1, BlockError::InvalidHash);
             }
 
-            if !is_genesis && block.prev_hash.is_none() {
-                return Err(format!("Block {} doesn't have prev_hash", block_num));
+            if !is_genesis && block.header.prev_hash.is_none() {
+                return invalid(block_num as u64 - 1, BlockError::MissingPrevHash);
             }
 
-            if is_genesis && block.prev_hash.is_some() {
-                return Err("Genesis block shouldn't have prev_hash".to_string());
+            if is_genesis && block.header.prev_hash.is_some() {
+                return invalid(0, BlockError::UnexpectedPrevHash);
             }
 
             if block_num != self.blocks.len() {
                 if let Some(prev_block_hash) = &prev_block_hash {
                     if prev_block_hash != &block.hash.clone().unwrap() {
-                        return Err(format!(
-                            "Block {} prev_hash doesn't match Block {} hash",
-                            block_num + 1,
-                            block_num
-                        ));
+                        // the child's prev_hash is the one which doesn't match
+                        return invalid(block_num as u64, BlockError::PrevHashMismatch);
                     }
                 }
             }
 
-            prev_block_hash = block.prev_hash.clone();
+            prev_block_hash = block.header.prev_hash.clone();
             block_num -= 1;
         }
 
         Ok(())
     }
 
+    fn add_orphan(&mut self, block: Block, now: Timestamp) -> Result<(), BlockError> {
+        if !block.verify() {
+            return Err(BlockError::InvalidHash);
+        }
+
+        // the target in force is unknown without the parent, so the block has to meet its own,
+        // which can't be easier than the head's
+        let hash = block.hash.clone().unwrap();
+        match Target::from_compact(block.header.bits) {
+            Some(target) if target <= self.current_target && target.is_met_by(&hash) => {}
+            _ => return Err(BlockError::InsufficientWork),
+        }
+
+        self.limits.check(&block)?;
+
+        let parent = block.header.prev_hash.clone().unwrap();
+        let known = self.orphans.get(&parent)
+            .is_some_and(|siblings| siblings.iter().any(|(_, orphan)| orphan.hash == block.hash));
+        if known {
+            return Err(BlockError::AlreadyExists);
+        }
+
+        self.expire_orphans(now);
+        if self.orphans_len() >= MAX_ORPHAN_BLOCKS {
+            self.evict_oldest_orphan();
+        }
+        self.orphans.entry(parent).or_default().push((now, block));
+
+        Ok(())
+    }
+
+    fn expire_orphans(&mut self, now: Timestamp) {
+        for siblings in self.orphans.values_mut() {
+            siblings.retain(|(received, _)| now < received.saturating_add(ORPHAN_EXPIRY));
+        }
+        self.orphans.retain(|_, siblings| !siblings.is_empty());
+    }
+
+    fn evict_oldest_orphan(&mut self) {
+        let oldest = self.orphans.iter()
+            .flat_map(|(parent, siblings)| {
+                siblings.iter().enumerate().map(move |(index, (received, _))| (*received, parent, index))
+            })
+            .min_by_key(|(received, _, _)| *received)
+            .map(|(_, parent, index)| (parent.clone(), index));
+
+        if let Some((parent, index)) = oldest {
+            let siblings = self.orphans.get_mut(&parent).unwrap();
+            siblings.remove(index);
+            if siblings.is_empty() {
+                self.orphans.remove(&parent);
+            }
+        }
+    }
+
+    fn connect_orphans(&mut self, parent: Hash, now: Timestamp) {
+        let mut parents = vec![parent];
+        let mut invalid = Vec::new();
+        while let Some(parent) = parents.pop() {
+            for (_, orphan) in self.orphans.remove(&parent).unwrap_or_default() {
+                let hash = orphan.hash.clone().unwrap();
+                match self.connect_block(orphan, now) {
+                    Ok(()) => parents.push(hash),
+                    Err(_) => invalid.push(hash),
+                }
+            }
+        }
+
+        // descendants of an invalid block can never be appended
+        while let Some(parent) = invalid.pop() {
+            for (_, orphan) in self.orphans.remove(&parent).unwrap_or_default() {
+                invalid.push(orphan.hash.unwrap());
+            }
+        }
+    }
+
+    /// Replays the main chain from genesis on an empty state, with every rule `append_block`
+    /// enforces: targets, proof-of-work, timestamps and transaction execution.
+    pub fn validate_full(&self) -> Result<(), InvalidBlock> {
+        let mut replay = Blockchain {
+            difficulty: self.difficulty.clone(),
+            rewards: self.rewards,
+            fee_market: self.fee_market,
+            limits: self.limits,
+            configured_chain_id: self.configured_chain_id,
+            supply_cap: self.supply_cap,
+            ..Blockchain::new()
+        };
+        replay.update_head_state();
+        let now = generate_timestamp();
+
+        for (height, block) in self.blocks.iter_from_genesis().enumerate() {
+            let invalid = |reason| InvalidBlock { height: height as u64, reason };
+
+            if block.header.prev_hash != replay.get_last_block_hash() {
+                return Err(invalid(BlockError::PrevHashMismatch));
+            }
+            replay.append_block_at(block.clone(), now).map_err(invalid)?;
+        }
+
+        Ok(())
+    }
+
     pub fn get_last_block_hash(&self) -> Option<Hash> {
         self.blocks.head().map(|block| block.hash())
     }
 
-    fn target_adjust(&mut self, block_timestamp: Timestamp) {
-        let actual = block_timestamp - self.last_timestamp.clone();
-        let mut ratio: f64 = (actual as f64)/EXPECTED_TIME;
-        if ratio < 0.25 {
-            ratio = 0.25;
-        } else if ratio > 4.0 {
-            ratio = 4.0;
+    /// Block of the main chain at `height`.
+    pub fn get_block_by_height(&self, height: u64) -> Option<&Block> {
+        self.blocks.get(height as usize)
+    }
+
+    /// Known block with the given hash, on the main chain or on a side branch.
+    pub fn get_block_by_hash(&self, hash: &Hash) -> Option<&Block> {
+        self.blocks.get_by_hash(hash).or_else(|| self.side_blocks.get(hash))
+    }
+
+    /// Target the next block on top of the head has to meet.
+    pub fn get_current_target(&self) -> Target {
+        self.current_target
+    }
+
+    /// Base fee of the next block on top of the head.
+    pub fn get_next_base_fee(&self) -> Balance {
+        self.next_base_fee(&self.get_last_block_hash()).unwrap_or_default()
+    }
+
+    /// Cumulative proof-of-work of the main chain.
+    pub fn total_work(&self) -> Work {
+        self.blocks.head()
+            .and_then(|block| block.hash.as_ref())
+            .and_then(|hash| self.block_index.get(hash))
+            .map_or(Work::ZERO, |meta| meta.total_work)
+    }
+
+    /// Number of known blocks which are not on the main chain.
+    pub fn side_blocks_len(&self) -> usize {
+        self.side_blocks.len()
+    }
+
+    // Height of a child of `prev_hash`, None if the parent is unknown
+    pub(crate) fn next_height(&self, prev_hash: &Option<Hash>) -> Option<u64> {
+        match prev_hash {
+            Some(prev_hash) => self.block_index.get(prev_hash).map(|meta| meta.height + 1),
+            None => Some(0),
         }
+    }
 
-        let start_exp = &self.compact_form[..2];      // exponent
-        let start_coef  = &self.compact_form[2..];    // coefficient
-        let dec_exp = u64::from_str_radix(start_exp, 16);
-        let dec_coef = u64::from_str_radix(start_coef, 16);
-        let dec_new_coef: f64 = (dec_coef.unwrap() as f64) * ratio;
+    // Target a child of `prev_hash` has to meet, None if the parent is unknown
+    pub(crate) fn next_target(&self, prev_hash: &Option<Hash>) -> Option<Target> {
+        let height = self.next_height(prev_hash)?;
 
-        let new_compact_form =
-            Blockchain::check_target_adjust(dec_exp.unwrap(), dec_new_coef, start_coef, ratio);
-        let new_target = u64::from_str_radix(&new_compact_form, 16);
+        let history: Vec<BlockTiming> = self.ancestors(prev_hash, self.difficulty.0.window())
+            .iter()
+            .rev()
+            .map(|meta| BlockTiming { timestamp: meta.timestamp, target: meta.target })
+            .collect();
 
-        if new_target.clone().unwrap() >= MAX_TARGET {
-            self.current_target = MAX_TARGET;
-            self.compact_form = MAX_COMPACT_FORM.to_string();
-        } else {
-            self.current_target = new_target.unwrap();
-            self.compact_form = new_compact_form;
+        Some(self.difficulty.0.next_target(height, &history))
+    }
+
+    // Base fee of a child of `prev_hash`, None if the parent is unknown
+    pub(crate) fn next_base_fee(&self, prev_hash: &Option<Hash>) -> Option<Balance> {
+        match prev_hash {
+            Some(prev_hash) => self.block_index.get(prev_hash)
+                .map(|meta| self.fee_market.next_base_fee(meta.base_fee, meta.size)),
+            None => Some(0),
         }
     }
 
-    fn check_target_adjust(dec_exp: u64, mut dec_new_coef: f64, start_coef: &str, ratio: f64) -> String {
-        if (start_coef.chars().nth(2).unwrap() == '0') && (ratio < 1.0)  {
-            dec_new_coef *= 16.0;
+    // Up to `count` blocks ending with `prev_hash`, most recent first
+    fn ancestors(&self, prev_hash: &Option<Hash>, count: usize) -> Vec<&BlockMeta> {
+        let mut ancestors = Vec::new();
+        let mut cursor = prev_hash.as_ref();
+        while ancestors.len() < count {
+            match cursor.and_then(|hash| self.block_index.get(hash)) {
+                Some(meta) => {
+                    ancestors.push(meta);
+                    cursor = meta.prev_hash.as_ref();
+                }
+                None => break,
+            }
         }
 
-        let mut hex_new_coef = format!("{:x}", dec_new_coef.clone().ceil() as i64);
-        let mut dec_new_exp: u64 = dec_exp;
-        if hex_new_coef.len() == COEFFICIENT_LENGTH-1 {
-            hex_new_coef += "0";
-            dec_new_exp -= 1;
+        ancestors
+    }
+
+    // Median timestamp of the last MEDIAN_TIME_SPAN blocks ending with `prev_hash`
+    fn median_time_past(&self, prev_hash: &Option<Hash>) -> Option<Timestamp> {
+        let mut timestamps: Vec<Timestamp> = self.ancestors(prev_hash, MEDIAN_TIME_SPAN)
+            .iter()
+            .map(|meta| meta.timestamp)
+            .collect();
+        timestamps.sort_unstable();
+
+        timestamps.get(timestamps.len() / 2).copied()
+    }
+
+    fn check_timestamp(&self, block: &Block, now: Timestamp) -> Result<(), BlockError> {
+        if block.header.timestamp > now.saturating_add(MAX_FUTURE_DRIFT) {
+            return Err(BlockError::TimestampInFuture(block.header.timestamp));
         }
-        else if hex_new_coef.len() > COEFFICIENT_LENGTH {
-            hex_new_coef = "0".to_owned() + &hex_new_coef[..COEFFICIENT_LENGTH-1].to_string();
-            dec_new_exp += 1;
+
+        match self.median_time_past(&block.header.prev_hash) {
+            Some(median) if block.header.timestamp < median => Err(BlockError::TimestampBeforeMedian {
+                timestamp: block.header.timestamp,
+                median,
+            }),
+            _ => Ok(()),
         }
+    }
 
-        hex::encode(vec![dec_new_exp as u8]) + &hex_new_coef
+    // Context of the transactions in the next block on top of the head
+    fn execution_context(&self, miner: Option<AccountId>) -> ExecutionContext {
+        let is_genesis = self.blocks.is_empty();
+        let height = self.next_height(&self.get_last_block_hash()).unwrap();
+
+        ExecutionContext {
+            // the genesis hash isn't known yet when its own transactions are signed
+            chain_id: if is_genesis { self.configured_chain_id.unwrap_or(0) } else { self.chain_id() },
+            is_genesis,
+            height,
+            subsidy: self.rewards.subsidy(height),
+            base_fee: self.get_next_base_fee(),
+            miner,
+        }
+    }
+
+    // Executes a block on top of the head and makes it the new head
+    fn connect_head(&mut self, block: Block) -> Result<(), BlockError> {
+        let (changes, supply) = self.execute_block(&block)?;
+        let undo = BlockUndo::apply(changes, &mut self.accounts, self.total_supply);
+        self.total_supply = supply;
+        self.blocks.append(block);
+        self.undo_log.push(undo);
+
+        Ok(())
+    }
+
+    fn disconnect_head(&mut self) -> Option<Block> {
+        let block = self.blocks.pop()?;
+        let undo = self.undo_log.pop().unwrap();
+        self.total_supply = undo.revert(&mut self.accounts);
+
+        Some(block)
+    }
+
+    // Forgets a block and the side blocks descending from it
+    fn forget(&mut self, hash: Hash) {
+        let mut forgotten = vec![hash];
+        while let Some(hash) = forgotten.pop() {
+            self.block_index.remove(&hash);
+            let children: Vec<Hash> = self.side_blocks.values()
+                .filter(|block| block.header.prev_hash.as_ref() == Some(&hash))
+                .map(|block| block.hash.clone().unwrap())
+                .collect();
+            for child in children {
+                self.side_blocks.remove(&child);
+                forgotten.push(child);
+            }
+        }
+    }
+
+    // Executes the transactions of a block on top of the head without changing the state,
+    // returns the changed accounts (None if removed) and the total supply after the block
+    fn execute_block(&self, block: &Block) -> Result<(HashMap<AccountId, Option<Account>>, Balance), BlockError> {
+        let context = self.execution_context(block.miner().cloned());
+
+        let mut state = StateOverlay::new(&self.accounts);
+        let mut supply = self.total_supply;
+        for (index, tx) in block.transactions.iter().enumerate() {
+            let res = tx.execute(&mut state, &context).and_then(|()| {
+                supply = self.next_supply(supply, tx, &context)?;
+                Ok(())
+            });
+            if let Err(error) = res {
+                return Err(BlockError::Transaction { index, error });
+            }
+        }
+
+        Ok((state.into_changes(), supply))
+    }
+
+    fn next_supply(&self, supply: Balance, tx: &Transaction, context: &ExecutionContext) -> Result<Balance, TxError> {
+        let (issued, burned) = tx.supply_change(context);
+        let next = supply.checked_add(issued)
+            .filter(|next| *next <= self.supply_cap)
+            .ok_or(StateError::SupplyCapExceeded { supply: supply.saturating_add(issued), cap: self.supply_cap })?;
+
+        // burned coins were on some account, which can't hold more than the supply
+        Ok(next.saturating_sub(burned))
+    }
+
+    fn reorganize(&mut self, new_head: Hash) -> Result<(), BlockError> {
+        // Everything below the side branch is on the main chain, the first such block is the fork point
+        let mut branch = Vec::new();
+        let mut fork_point = Some(new_head);
+        while let Some(block) = fork_point.as_ref().and_then(|hash| self.side_blocks.remove(hash)) {
+            fork_point = block.header.prev_hash.clone();
+            branch.push(block);
+        }
+        branch.reverse();
+
+        // Undo the main chain down to the fork point
+        let mut detached = Vec::new();
+        while self.get_last_block_hash() != fork_point {
+            detached.push(self.disconnect_head().unwrap());
+        }
+
+        let mut applied = 0;
+        let mut failure = None;
+        for block in &branch {
+            if let Err(error) = self.connect_head(block.clone()) {
+                failure = Some((block.hash.clone().unwrap(), error));
+                break;
+            }
+            applied += 1;
+        }
+
+        if let Some((hash, error)) = failure {
+            // The new branch is invalid from this block on: forget it, along with the side blocks
+            // built on it, and restore the old main chain
+            for block in &branch[applied..] {
+                self.forget(block.hash.clone().unwrap());
+            }
+            for _ in 0..applied {
+                let block = self.disconnect_head().unwrap();
+                self.side_blocks.insert(block.hash.clone().unwrap(), block);
+            }
+            // they were connected on the same state before
+            while let Some(block) = detached.pop() {
+                self.connect_head(block)?;
+            }
+            self.update_head_state();
+
+            return Err(BlockError::InvalidBranch { hash, error: Box::new(error) });
+        }
+
+        self.update_head_state();
+        // detached blocks are head first
+        self.resubmit_transactions(detached.iter().rev());
+        for block in detached {
+            self.side_blocks.insert(block.hash.clone().unwrap(), block);
+        }
+
+        Ok(())
     }
 
+    // Offers the transactions of blocks taken off the main chain back to the pool, oldest block
+    // first. Coinbases and the transactions which aren't valid on the new head are dropped.
+    fn resubmit_transactions<'a>(&mut self, blocks: impl IntoIterator<Item = &'a Block>) {
+        for block in blocks {
+            for tx in block.transactions.iter().filter(|tx| !tx.is_coinbase()) {
+                let _ = self.submit_transaction(tx.clone());
+            }
+        }
+    }
+
+    fn prune_mempool(&mut self) {
+        let mut pool = std::mem::take(&mut self.transaction_pool);
+        pool.prune(self);
+        self.transaction_pool = pool;
+    }
+
+    fn update_head_state(&mut self) {
+        self.current_target = self.next_target(&self.get_last_block_hash()).unwrap_or(Target::MAX);
+    }
+}
+
+// The last 8 bytes of the hash: the proof-of-work pushes the leading ones toward zero, so those
+// would be shared by many chains
+fn chain_id_from_hash(hash: &Hash) -> ChainId {
+    let mut bytes = [0u8; 8];
+    if let Ok(decoded) = hex::decode(hash) {
+        for (byte, decoded) in bytes.iter_mut().rev().zip(decoded.into_iter().rev()) {
+            *byte = decoded;
+        }
+    }
+
+    ChainId::from_be_bytes(bytes)
 }
 
 #[cfg(test)]
@@ -185,5 +834,15 @@ mod tests {
     fn test_new() {
         let bc = Blockchain::new();
         assert_eq!(bc.get_last_block_hash(), None);
+        assert_eq!(bc.total_work(), Work::ZERO);
+        assert_eq!(bc.chain_id(), 0);
+        assert_eq!(Blockchain::new().with_chain_id(7).chain_id(), 7);
+    }
+
+    #[test]
+    fn test_chain_id_from_hash() {
+        assert_eq!(chain_id_from_hash(&"000000ff0102030405060708".to_string()), 0x0102_0304_0506_0708);
+        assert_eq!(chain_id_from_hash(&"0102".to_string()), 0x0102);
+        assert_eq!(chain_id_from_hash(&"not hex".to_string()), 0);
     }
 }
@@ -1,81 +1,95 @@
-#[derive(Default, Debug)]
-pub struct Node<T> {
-    data: T,
-    prev: Option<Box<Node<T>>>,
+use crate::traits::Hashable;
+use crate::types::Hash;
+use std::collections::HashMap;
+use std::iter::Rev;
+use std::slice;
+
+/// Items from genesis to head, indexed by height and by hash. Each item is indexed by its hash
+/// at the time it was appended.
+#[derive(Debug)]
+pub struct Chain<T> {
+    items: Vec<T>,
+    // in item order
+    hashes: Vec<Hash>,
+    heights: HashMap<Hash, usize>,
 }
 
-#[derive(Default, Debug)]
-pub struct Chain<T> {
-    head: Option<Box<Node<T>>>,
-    len: usize,
+impl<T> Default for Chain<T> {
+    fn default() -> Self {
+        Self {
+            items: Vec::new(),
+            hashes: Vec::new(),
+            heights: HashMap::new(),
+        }
+    }
 }
 
-impl<'a, T> Iterator for ChainIter<'a, T> {
-    type Item = &'a T;
+impl<T: Hashable> Chain<T> {
+    pub fn new() -> Self {
+        Default::default()
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.next.take().map(|node| {
-            self.next = node.prev.as_deref();
-            &node.data
-        })
+    pub fn append(&mut self, item: T) {
+        let hash = item.hash();
+        self.heights.insert(hash.clone(), self.items.len());
+        self.hashes.push(hash);
+        self.items.push(item);
     }
-}
 
-impl<'a, T> Iterator for ChainIterMut<'a, T> {
-    type Item = &'a mut T;
+    pub fn pop(&mut self) -> Option<T> {
+        let hash = self.hashes.pop()?;
+        self.heights.remove(&hash);
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.next.take().map(|node| {
-            self.next = node.prev.as_deref_mut();
-            &mut node.data
-        })
+        self.items.pop()
     }
-}
 
-pub struct ChainIter<'a, T> {
-    next: Option<&'a Node<T>>,
-}
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
 
-pub struct ChainIterMut<'a, T> {
-    next: Option<&'a mut Node<T>>,
-}
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
 
-impl<T: Default> Chain<T> {
-    pub fn new() -> Self {
-        Default::default()
+    pub fn head(&self) -> Option<&T> {
+        self.items.last()
     }
 
-    pub fn append(&mut self, item: T) {
-        let head = self.head.take();
-        let node = Box::new(Node {
-            data: item,
-            prev: head,
-        });
-        self.head = Some(node);
-        self.len += 1;
+    pub fn genesis(&self) -> Option<&T> {
+        self.items.first()
     }
 
-    pub fn len(&self) -> usize {
-        self.len
+    /// Item at `height`, the genesis being at 0.
+    pub fn get(&self, height: usize) -> Option<&T> {
+        self.items.get(height)
     }
 
-    pub fn head(&self) -> Option<&T> {
-        match &self.head {
-            None => None,
-            Some(head) => Some(&head.data),
-        }
+    pub fn get_by_hash(&self, hash: &Hash) -> Option<&T> {
+        self.height_of(hash).map(|height| &self.items[height])
     }
 
-    pub fn iter(&self) -> ChainIter<T> {
-        ChainIter {
-            next: self.head.as_deref(),
-        }
+    pub fn height_of(&self, hash: &Hash) -> Option<usize> {
+        self.heights.get(hash).copied()
     }
 
-    pub fn iter_mut(&mut self) -> ChainIterMut<T> {
-        ChainIterMut {
-            next: self.head.as_deref_mut(),
-        }
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.heights.contains_key(hash)
+    }
+
+    /// Iterates from the head down to the genesis.
+    pub fn iter(&self) -> Rev<slice::Iter<'_, T>> {
+        self.items.iter().rev()
+    }
+
+    /// Iterates from the head down to the genesis. Changed items keep the hash they were
+    /// indexed with.
+    pub fn iter_mut(&mut self) -> Rev<slice::IterMut<'_, T>> {
+        self.items.iter_mut().rev()
+    }
+
+    /// Iterates from the genesis up to the head.
+    pub fn iter_from_genesis(&self) -> slice::Iter<'_, T> {
+        self.items.iter()
     }
 }
 
@@ -83,6 +97,12 @@ impl<T: Default> Chain<T> {
 mod tests {
     use super::*;
 
+    impl Hashable for u32 {
+        fn hash(&self) -> Hash {
+            self.to_string()
+        }
+    }
+
     #[test]
     fn test_append() {
         let mut chain = Chain::<u32>::new();
@@ -90,6 +110,22 @@ mod tests {
         chain.append(1);
 
         assert_eq!(chain.head(), Some(&1));
+        assert_eq!(chain.genesis(), Some(&3));
+    }
+
+    #[test]
+    fn test_pop() {
+        let mut chain = Chain::<u32>::new();
+        chain.append(1);
+        chain.append(2);
+
+        assert_eq!(chain.pop(), Some(2));
+        assert_eq!(chain.head(), Some(&1));
+        assert_eq!(chain.len(), 1);
+        assert!(!chain.contains(&"2".to_string()));
+        assert_eq!(chain.pop(), Some(1));
+        assert_eq!(chain.pop(), None);
+        assert!(chain.is_empty());
     }
 
     #[test]
@@ -100,8 +136,39 @@ mod tests {
         chain.append(3);
         chain.append(4);
 
-        for i in chain.iter() {
-            dbg!(i);
+        assert_eq!(chain.iter().copied().collect::<Vec<_>>(), vec![4, 3, 2, 1]);
+        assert_eq!(chain.iter_from_genesis().copied().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert_eq!(chain.iter().rev().copied().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_lookup() {
+        let mut chain = Chain::<u32>::new();
+        for item in 10..20 {
+            chain.append(item);
         }
+
+        assert_eq!(chain.get(0), Some(&10));
+        assert_eq!(chain.get(9), Some(&19));
+        assert_eq!(chain.get(10), None);
+        assert_eq!(chain.get_by_hash(&"15".to_string()), Some(&15));
+        assert_eq!(chain.height_of(&"15".to_string()), Some(5));
+        assert_eq!(chain.get_by_hash(&"20".to_string()), None);
+
+        // the index keeps the hash an item was appended with
+        *chain.iter_mut().next().unwrap() = 42;
+        assert_eq!(chain.get_by_hash(&"19".to_string()), Some(&42));
+        assert_eq!(chain.pop(), Some(42));
+        assert!(!chain.contains(&"19".to_string()));
+    }
+
+    #[test]
+    fn test_drop_long_chain() {
+        let mut chain = Chain::<u32>::new();
+        for item in 0..100_000 {
+            chain.append(item);
+        }
+
+        drop(chain);
     }
 }
@@ -0,0 +1,178 @@
+use crate::traits::DifficultyAlgorithm;
+use crate::types::{EXPECTED_TIME, Target, Timestamp, U256};
+
+/// What a difficulty algorithm knows about a past block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockTiming {
+    pub timestamp: Timestamp,
+    pub target: Target,
+}
+
+// in milliseconds, like the expected block time
+fn solve_time(prev: &BlockTiming, block: &BlockTiming) -> u64 {
+    block.timestamp.saturating_sub(prev.timestamp).saturating_mul(1000)
+}
+
+/// Retargets on every block from the time between the parent and its own parent.
+#[derive(Debug, Clone)]
+pub struct PerBlock {
+    expected_time: u64,
+}
+
+impl PerBlock {
+    pub fn new(expected_time: u64) -> Self {
+        Self { expected_time }
+    }
+}
+
+impl Default for PerBlock {
+    fn default() -> Self {
+        PerBlock::new(EXPECTED_TIME)
+    }
+}
+
+impl DifficultyAlgorithm for PerBlock {
+    fn window(&self) -> usize {
+        2
+    }
+
+    fn next_target(&self, _height: u64, history: &[BlockTiming]) -> Target {
+        match history {
+            [.., prev, parent] => parent.target.retarget(solve_time(prev, parent), self.expected_time),
+            [parent] => parent.target,
+            [] => Target::MAX,
+        }
+    }
+}
+
+/// Bitcoin-style retargeting: the target stays the same for `interval` blocks and is then
+/// scaled by how long the last `interval` blocks actually took.
+#[derive(Debug, Clone)]
+pub struct FixedInterval {
+    interval: u64,
+    expected_time: u64,
+}
+
+impl FixedInterval {
+    pub fn new(interval: u64, expected_time: u64) -> Self {
+        Self {
+            interval: interval.max(1),
+            expected_time,
+        }
+    }
+}
+
+impl DifficultyAlgorithm for FixedInterval {
+    fn window(&self) -> usize {
+        self.interval as usize + 1
+    }
+
+    fn next_target(&self, height: u64, history: &[BlockTiming]) -> Target {
+        let (first, parent) = match (history.first(), history.last()) {
+            (Some(first), Some(parent)) => (first, parent),
+            _ => return Target::MAX,
+        };
+        let blocks = (history.len() - 1) as u64;
+        if !height.is_multiple_of(self.interval) || blocks == 0 {
+            return parent.target;
+        }
+
+        parent.target.retarget(solve_time(first, parent), blocks.saturating_mul(self.expected_time))
+    }
+}
+
+/// Linearly weighted moving average: the average target of the last `window` blocks, scaled by
+/// their solve times with the most recent ones weighted the most.
+#[derive(Debug, Clone)]
+pub struct Lwma {
+    window: usize,
+    expected_time: u64,
+}
+
+impl Lwma {
+    pub fn new(window: usize, expected_time: u64) -> Self {
+        Self {
+            window: window.max(1),
+            expected_time,
+        }
+    }
+}
+
+impl DifficultyAlgorithm for Lwma {
+    fn window(&self) -> usize {
+        self.window + 1
+    }
+
+    fn next_target(&self, _height: u64, history: &[BlockTiming]) -> Target {
+        if history.len() < 2 {
+            return history.last().map_or(Target::MAX, |parent| parent.target);
+        }
+
+        let blocks = (history.len() - 1) as u64;
+        let mut weighted_time: u64 = 0;
+        let mut target_sum = U256::ZERO;
+        for (weight, pair) in (1..).zip(history.windows(2)) {
+            // a single slow block shouldn't drop the difficulty too much
+            let time = solve_time(&pair[0], &pair[1]).min(6 * self.expected_time);
+            weighted_time = weighted_time.saturating_add(weight * time);
+            target_sum = target_sum.saturating_add(pair[1].target.as_u256());
+        }
+
+        let expected_weighted_time = (blocks * (blocks + 1) / 2).saturating_mul(self.expected_time);
+        Target::new(target_sum.div_u64(blocks)).retarget(weighted_time, expected_weighted_time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(target: Target, timestamps: &[Timestamp]) -> Vec<BlockTiming> {
+        timestamps.iter().map(|&timestamp| BlockTiming { timestamp, target }).collect()
+    }
+
+    #[test]
+    fn test_per_block() {
+        let algorithm = PerBlock::new(2000);
+        let target = Target::from_compact(0x1f00ffff).unwrap();
+
+        assert_eq!(algorithm.next_target(0, &[]), Target::MAX);
+        assert_eq!(algorithm.next_target(1, &history(target, &[10])), target);
+        assert_eq!(algorithm.next_target(2, &history(target, &[10, 12])), target);
+        assert_eq!(algorithm.next_target(2, &history(target, &[10, 14])), target.retarget(2, 1));
+        assert_eq!(algorithm.next_target(2, &history(target, &[10, 10])), target.retarget(1, 4));
+    }
+
+    #[test]
+    fn test_fixed_interval() {
+        let algorithm = FixedInterval::new(4, 1000);
+        let target = Target::from_compact(0x1f00ffff).unwrap();
+
+        // no retarget in the middle of an interval, however long the blocks took
+        assert_eq!(algorithm.next_target(3, &history(target, &[0, 100, 200])), target);
+
+        // 4 blocks in 8 seconds instead of 4
+        let timings = history(target, &[0, 2, 4, 6, 8]);
+        assert_eq!(algorithm.next_target(8, &timings), target.retarget(2, 1));
+
+        // on schedule
+        let timings = history(target, &[0, 1, 2, 3, 4]);
+        assert_eq!(algorithm.next_target(8, &timings), target);
+    }
+
+    #[test]
+    fn test_lwma() {
+        let algorithm = Lwma::new(4, 1000);
+        let target = Target::from_compact(0x1f00ffff).unwrap();
+
+        assert_eq!(algorithm.next_target(0, &[]), Target::MAX);
+        assert_eq!(algorithm.next_target(5, &history(target, &[0, 1, 2, 3, 4])), target);
+        assert!(algorithm.next_target(5, &history(target, &[0, 2, 4, 6, 8])) > target);
+        assert!(algorithm.next_target(5, &history(target, &[0, 0, 0, 0, 0])) < target);
+
+        // recent blocks weigh more
+        let slow_then_fast = algorithm.next_target(5, &history(target, &[0, 3, 6, 6, 6]));
+        let fast_then_slow = algorithm.next_target(5, &history(target, &[0, 0, 0, 3, 6]));
+        assert!(slow_then_fast < fast_then_slow);
+    }
+}
@@ -0,0 +1,201 @@
+//! Canonical binary encoding, the input to every hash and signature.
+//!
+//! Building blocks:
+//! - integers are fixed width and big-endian
+//! - `bool` and `Option` are a `0` or `1` byte, followed by the value for `Some`
+//! - strings are a `u32` byte length followed by the UTF-8 bytes
+//! - sequences are a `u32` item count followed by the items
+//! - byte arrays (public keys, signatures) are the bytes without a length
+//! - enums are a `u8` variant tag followed by the fields, structs are their fields in order
+//!
+//! Chain types:
+//! - `BlockHeader`: the fixed `HEADER_SIZE` bytes of `BlockHeader::to_bytes`
+//! - `Block`: the header, then the transactions. The block hash is derived from the header
+//!   and isn't encoded
+//! - `Transaction`: nonce `u128`, fee `u128`, timestamp `u64`, sender `Option<String>`, data,
+//!   signature `Option<[u8; 64]>`. The transaction hash and the signature cover everything but
+//!   the signature, the Merkle leaf of the transaction covers all of it
+//! - `TransactionData`: `0` CreateAccount (account ID, public key), `1` MintInitialSupply
+//!   (to, amount `u128`), `2` Transfer (to, amount `u128`), `3` Coinbase (to, amount `u128`,
+//!   height `u64`)
+//! - `Account`: type (`0` User, `1` Contract), balance `u128`, public key, nonce `u128`
+//!
+//! Decoding rejects anything but the canonical encoding, so every value has exactly one.
+
+use crate::traits::{Decode, Encode};
+use crate::types::{DecodeError, PK};
+use std::convert::TryFrom;
+
+pub(crate) fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], DecodeError> {
+    if input.len() < len {
+        return Err(DecodeError::UnexpectedEnd { needed: len - input.len() });
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+
+    Ok(bytes)
+}
+
+pub(crate) fn decode_tag(input: &mut &[u8], variants: u8) -> Result<u8, DecodeError> {
+    let tag = u8::decode_from(input)?;
+    if tag >= variants {
+        return Err(DecodeError::InvalidTag(tag));
+    }
+
+    Ok(tag)
+}
+
+fn encode_len(len: usize, out: &mut Vec<u8>) {
+    u32::try_from(len).expect("length doesn't fit in u32").encode_to(out);
+}
+
+macro_rules! impl_integer {
+    ($($int:ty),*) => {$(
+        impl Encode for $int {
+            fn encode_to(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_be_bytes());
+            }
+        }
+
+        impl Decode for $int {
+            fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+                Ok(<$int>::from_be_bytes(Decode::decode_from(input)?))
+            }
+        }
+    )*};
+}
+
+impl_integer!(u8, u32, u64, u128);
+
+impl<const N: usize> Encode for [u8; N] {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+}
+
+impl<const N: usize> Decode for [u8; N] {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let mut bytes = [0u8; N];
+        bytes.copy_from_slice(take(input, N)?);
+
+        Ok(bytes)
+    }
+}
+
+impl Encode for bool {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        (*self as u8).encode_to(out);
+    }
+}
+
+impl Decode for bool {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(decode_tag(input, 2)? == 1)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.is_some().encode_to(out);
+        if let Some(value) = self {
+            value.encode_to(out);
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match bool::decode_from(input)? {
+            true => Ok(Some(T::decode_from(input)?)),
+            false => Ok(None),
+        }
+    }
+}
+
+impl Encode for String {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        encode_len(self.len(), out);
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Decode for String {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = u32::decode_from(input)? as usize;
+        let bytes = take(input, len)?;
+
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        encode_len(self.len(), out);
+        for item in self {
+            item.encode_to(out);
+        }
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let count = u32::decode_from(input)? as usize;
+        // every item takes at least a byte, a bogus count can't allocate more than the input
+        let mut items = Vec::with_capacity(count.min(input.len()));
+        for _ in 0..count {
+            items.push(T::decode_from(input)?);
+        }
+
+        Ok(items)
+    }
+}
+
+impl Encode for PK {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Decode for PK {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let bytes: [u8; 32] = Decode::decode_from(input)?;
+
+        PK::from_bytes(&bytes).map_err(|_| DecodeError::InvalidPublicKey)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_primitives() {
+        assert_eq!(0x0102u32.encode(), vec![0, 0, 1, 2]);
+        assert_eq!(Some(7u8).encode(), vec![1, 7]);
+        assert_eq!(None::<u8>.encode(), vec![0]);
+        assert_eq!("ab".to_string().encode(), vec![0, 0, 0, 2, b'a', b'b']);
+        assert_eq!(vec![1u8, 2].encode(), vec![0, 0, 0, 2, 1, 2]);
+
+        let value = (u128::MAX - 1, Some("alice".to_string()), vec![1u64, 2, 3]);
+        let mut out = Vec::new();
+        value.0.encode_to(&mut out);
+        value.1.encode_to(&mut out);
+        value.2.encode_to(&mut out);
+
+        let mut input = out.as_slice();
+        assert_eq!(u128::decode_from(&mut input), Ok(value.0));
+        assert_eq!(Option::<String>::decode_from(&mut input), Ok(value.1));
+        assert_eq!(Vec::<u64>::decode_from(&mut input), Ok(value.2));
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn test_non_canonical_input() {
+        assert_eq!(u64::decode(&[0; 7]), Err(DecodeError::UnexpectedEnd { needed: 1 }));
+        assert_eq!(u64::decode(&[0; 9]), Err(DecodeError::TrailingBytes(1)));
+        assert_eq!(bool::decode(&[2]), Err(DecodeError::InvalidTag(2)));
+        assert_eq!(Option::<u8>::decode(&[2, 0]), Err(DecodeError::InvalidTag(2)));
+        assert_eq!(String::decode(&[0, 0, 0, 2, 0xff, 0xff]), Err(DecodeError::InvalidUtf8));
+        assert!(Vec::<u8>::decode(&[0xff, 0xff, 0xff, 0xff, 1]).is_err());
+    }
+}
@@ -0,0 +1,380 @@
+use crate::types::{AccountId, Balance, Hash, Timestamp};
+use std::fmt;
+
+/// Any error of the crate, each kind converts into it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Block(BlockError),
+    Transaction(TxError),
+    State(StateError),
+    Mempool(MempoolError),
+    Decode(DecodeError),
+    Mining(MiningError),
+    InvalidBlock(InvalidBlock),
+}
+
+/// Why a block can't be appended to the chain.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockError {
+    MissingPrevHash,
+    // a genesis block with a prev_hash
+    UnexpectedPrevHash,
+    PrevHashMismatch,
+    UnknownParent(Hash),
+    InvalidHash,
+    AlreadyExists,
+    InvalidHeight { height: u64, expected: u64 },
+    InvalidBits(u32),
+    InsufficientWork,
+    InvalidBaseFee { base_fee: Balance, expected: Balance },
+    NoTransactions,
+    // every block but the genesis starts with a coinbase
+    MissingCoinbase,
+    TooManyTransactions { count: usize, limit: usize },
+    TooLarge { size: usize, limit: usize },
+    MisplacedCoinbase { index: usize },
+    TimestampInFuture(Timestamp),
+    TimestampBeforeMedian { timestamp: Timestamp, median: Timestamp },
+    // the transaction at `index` of the block failed
+    Transaction { index: usize, error: TxError },
+    // the block with `hash` on the branch the chain was reorganizing to is invalid
+    InvalidBranch { hash: Hash, error: Box<BlockError> },
+}
+
+/// Why a transaction can't be executed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TxError {
+    MissingSender,
+    UnknownSender(AccountId),
+    UnknownAccount(AccountId),
+    MissingSignature,
+    InvalidSignature,
+    InvalidNonce { nonce: u128, expected: u128 },
+    InsufficientBalance,
+    MintOutsideGenesis,
+    InvalidCoinbaseHeight { height: u64, expected: u64 },
+    ExcessiveCoinbase { amount: Balance, subsidy: Balance },
+    // a transaction without a sender has nobody to pay its fee
+    UnexpectedFee,
+    // a mint or a coinbase isn't paid for by anyone
+    UnexpectedSender,
+    FeeBelowBaseFee { fee: Balance, base_fee: Balance },
+    State(StateError),
+}
+
+/// Why the world state refused a change.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StateError {
+    AccountExists(AccountId),
+    // the balance would exceed `Balance::MAX`
+    BalanceOverflow(AccountId),
+    SupplyCapExceeded { supply: Balance, cap: Balance },
+    // the storage behind the state failed, e.g. to write a batch
+    Backend(String),
+}
+
+/// Why the transaction pool refused a transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MempoolError {
+    AlreadyKnown,
+    NonceTaken { sender: AccountId, nonce: u128 },
+    Full,
+    // only the miner adds a coinbase
+    Coinbase,
+    Invalid(TxError),
+}
+
+/// Why bytes aren't a canonical encoding.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    UnexpectedEnd { needed: usize },
+    InvalidTag(u8),
+    InvalidUtf8,
+    InvalidPublicKey,
+    InvalidHeader,
+    // not 64 lowercase hex digits
+    InvalidHash,
+    TrailingBytes(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MiningError {
+    Cancelled,
+    Exhausted,
+}
+
+/// First block of the main chain which doesn't pass full validation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidBlock {
+    pub height: u64,
+    pub reason: BlockError,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Block(error) => error.fmt(f),
+            Error::Transaction(error) => error.fmt(f),
+            Error::State(error) => error.fmt(f),
+            Error::Mempool(error) => error.fmt(f),
+            Error::Decode(error) => error.fmt(f),
+            Error::Mining(error) => error.fmt(f),
+            Error::InvalidBlock(error) => error.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockError::MissingPrevHash => write!(f, "Non-genesis block doesn't have prev_hash"),
+            BlockError::UnexpectedPrevHash => write!(f, "Genesis block shouldn't have prev_hash"),
+            BlockError::PrevHashMismatch => write!(f, "prev_hash doesn't match the previous block hash"),
+            BlockError::UnknownParent(hash) => write!(f, "Block parent {} is unknown", hash),
+            BlockError::InvalidHash => write!(f, "Block has invalid hash"),
+            BlockError::AlreadyExists => write!(f, "Block already exists"),
+            BlockError::InvalidHeight { height, expected } => {
+                write!(f, "Block height {} doesn't match expected height {}", height, expected)
+            }
+            BlockError::InvalidBits(bits) => write!(f, "Block target bits {:08x} don't match expected target", bits),
+            BlockError::InsufficientWork => write!(f, "Block hash > target!"),
+            BlockError::InvalidBaseFee { base_fee, expected } => {
+                write!(f, "Block base fee {} doesn't match expected base fee {}", base_fee, expected)
+            }
+            BlockError::NoTransactions => write!(f, "Block has 0 transactions."),
+            BlockError::MissingCoinbase => write!(f, "Block doesn't start with a coinbase"),
+            BlockError::TooManyTransactions { count, limit } => {
+                write!(f, "Block has {} transactions, the limit is {}", count, limit)
+            }
+            BlockError::TooLarge { size, limit } => write!(f, "Block is {} bytes, the limit is {}", size, limit),
+            BlockError::MisplacedCoinbase { index } => {
+                write!(f, "Coinbase at index {} has to be the first transaction of the block", index)
+            }
+            BlockError::TimestampInFuture(timestamp) => {
+                write!(f, "Block timestamp {} is too far in the future", timestamp)
+            }
+            BlockError::TimestampBeforeMedian { timestamp, median } => {
+                write!(f, "Block timestamp {} is older than the median time past {}", timestamp, median)
+            }
+            BlockError::Transaction { index, error } => {
+                write!(f, "Error during execution of tx {}: {}", index, error)
+            }
+            BlockError::InvalidBranch { hash, error } => {
+                write!(f, "Error during reorganization, block {} is invalid: {}", hash, error)
+            }
+        }
+    }
+}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxError::MissingSender => write!(f, "Sender name doesn't exist!"),
+            TxError::UnknownSender(account_id) => write!(f, "Sender account {} doesn't exist", account_id),
+            TxError::UnknownAccount(account_id) => write!(f, "Account {} doesn't exist", account_id),
+            TxError::MissingSignature => write!(f, "Signature doesn't exist!"),
+            TxError::InvalidSignature => write!(f, "Verify signature error!"),
+            TxError::InvalidNonce { nonce, expected } => write!(f, "Invalid nonce {}, expected {}", nonce, expected),
+            TxError::InsufficientBalance => write!(f, "Sender haven't enough money!"),
+            TxError::MintOutsideGenesis => write!(f, "Initial supply can be minted only in genesis block."),
+            TxError::InvalidCoinbaseHeight { height, expected } => {
+                write!(f, "Coinbase height {} doesn't match block height {}", height, expected)
+            }
+            TxError::ExcessiveCoinbase { amount, subsidy } => {
+                write!(f, "Coinbase claims {}, but the block subsidy is {}", amount, subsidy)
+            }
+            TxError::UnexpectedFee => write!(f, "Transaction without a sender can't pay a fee"),
+            TxError::UnexpectedSender => write!(f, "Mint or coinbase can't have a sender"),
+            TxError::FeeBelowBaseFee { fee, base_fee } => write!(f, "Fee {} is below the base fee {}", fee, base_fee),
+            TxError::State(error) => error.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::AccountExists(account_id) => write!(f, "AccountId already exist: {}", account_id),
+            StateError::BalanceOverflow(account_id) => write!(f, "Balance of {} overflows", account_id),
+            StateError::SupplyCapExceeded { supply, cap } => {
+                write!(f, "Total supply {} would exceed the cap {}", supply, cap)
+            }
+            StateError::Backend(reason) => write!(f, "State backend failed: {}", reason),
+        }
+    }
+}
+
+impl fmt::Display for MempoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MempoolError::AlreadyKnown => write!(f, "Transaction is already in the pool"),
+            MempoolError::NonceTaken { sender, nonce } => {
+                write!(f, "Transaction with nonce {} from {} is already in the pool", nonce, sender)
+            }
+            MempoolError::Full => write!(f, "Transaction pool is full"),
+            MempoolError::Coinbase => write!(f, "Coinbase can only be added by the block's miner"),
+            MempoolError::Invalid(error) => error.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd { needed } => write!(f, "Unexpected end of input: {} more bytes needed", needed),
+            DecodeError::InvalidTag(tag) => write!(f, "Invalid tag {}", tag),
+            DecodeError::InvalidUtf8 => write!(f, "Invalid UTF-8 string"),
+            DecodeError::InvalidPublicKey => write!(f, "Invalid public key"),
+            DecodeError::InvalidHeader => write!(f, "Invalid block header"),
+            DecodeError::InvalidHash => write!(f, "Hash isn't 64 lowercase hex digits"),
+            DecodeError::TrailingBytes(count) => write!(f, "{} trailing bytes after the encoded value", count),
+        }
+    }
+}
+
+impl fmt::Display for MiningError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MiningError::Cancelled => write!(f, "Mining was cancelled"),
+            MiningError::Exhausted => write!(f, "Nonce space exhausted"),
+        }
+    }
+}
+
+impl fmt::Display for InvalidBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Block {} is invalid: {}", self.height, self.reason)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Block(error) => Some(error),
+            Error::Transaction(error) => Some(error),
+            Error::State(error) => Some(error),
+            Error::Mempool(error) => Some(error),
+            Error::Decode(error) => Some(error),
+            Error::Mining(error) => Some(error),
+            Error::InvalidBlock(error) => Some(error),
+        }
+    }
+}
+
+impl std::error::Error for BlockError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BlockError::Transaction { error, .. } => Some(error),
+            BlockError::InvalidBranch { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl std::error::Error for TxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TxError::State(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+impl std::error::Error for MempoolError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MempoolError::Invalid(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl std::error::Error for MiningError {}
+
+impl std::error::Error for InvalidBlock {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.reason)
+    }
+}
+
+impl From<BlockError> for Error {
+    fn from(error: BlockError) -> Self {
+        Error::Block(error)
+    }
+}
+
+impl From<TxError> for Error {
+    fn from(error: TxError) -> Self {
+        Error::Transaction(error)
+    }
+}
+
+impl From<StateError> for Error {
+    fn from(error: StateError) -> Self {
+        Error::State(error)
+    }
+}
+
+impl From<MempoolError> for Error {
+    fn from(error: MempoolError) -> Self {
+        Error::Mempool(error)
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(error: DecodeError) -> Self {
+        Error::Decode(error)
+    }
+}
+
+impl From<MiningError> for Error {
+    fn from(error: MiningError) -> Self {
+        Error::Mining(error)
+    }
+}
+
+impl From<InvalidBlock> for Error {
+    fn from(error: InvalidBlock) -> Self {
+        Error::InvalidBlock(error)
+    }
+}
+
+impl From<StateError> for TxError {
+    fn from(error: StateError) -> Self {
+        TxError::State(error)
+    }
+}
+
+impl From<TxError> for MempoolError {
+    fn from(error: TxError) -> Self {
+        MempoolError::Invalid(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn test_error_chain() {
+        let error = BlockError::Transaction {
+            index: 2,
+            error: TxError::InvalidNonce { nonce: 0, expected: 1 },
+        };
+        assert_eq!(error.to_string(), "Error during execution of tx 2: Invalid nonce 0, expected 1");
+        assert_eq!(error.source().unwrap().to_string(), "Invalid nonce 0, expected 1");
+
+        let error = Error::from(MempoolError::from(TxError::from(StateError::AccountExists("alice".to_string()))));
+        assert_eq!(error.to_string(), "AccountId already exist: alice");
+        assert!(error.source().unwrap().source().unwrap().source().is_some());
+
+        let error = Error::from(InvalidBlock { height: 3, reason: BlockError::NoTransactions });
+        assert_eq!(error.to_string(), "Block 3 is invalid: Block has 0 transactions.");
+        assert_eq!(error.source().unwrap().source().unwrap().to_string(), "Block has 0 transactions.");
+    }
+}
@@ -0,0 +1,58 @@
+use crate::types::{BASE_FEE_CHANGE_DENOMINATOR, Balance, TARGET_BLOCK_SIZE};
+
+/// EIP-1559-style base fee: it goes up when the parent block was larger than the target and
+/// down when it was smaller, by at most 1 / `change_denominator` per block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeeMarket {
+    // encoded bytes of the transactions paying the base fee
+    pub target_size: usize,
+    pub change_denominator: u128,
+}
+
+impl Default for FeeMarket {
+    fn default() -> Self {
+        FeeMarket::new(TARGET_BLOCK_SIZE, BASE_FEE_CHANGE_DENOMINATOR)
+    }
+}
+
+impl FeeMarket {
+    pub fn new(target_size: usize, change_denominator: u128) -> Self {
+        Self {
+            target_size: target_size.max(1),
+            change_denominator: change_denominator.max(1),
+        }
+    }
+
+    /// Base fee of a block whose parent had `parent_base_fee` and `parent_size`.
+    pub fn next_base_fee(&self, parent_base_fee: Balance, parent_size: usize) -> Balance {
+        let target = self.target_size as u128;
+        let used = parent_size as u128;
+        let delta = |difference: u128| {
+            parent_base_fee.saturating_mul(difference) / target / self.change_denominator
+        };
+
+        if used > target {
+            // a zero base fee has to be able to rise
+            parent_base_fee.saturating_add(delta(used - target).max(1))
+        } else {
+            parent_base_fee - delta(target - used)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_base_fee() {
+        let market = FeeMarket::new(10, 8);
+        assert_eq!(market.next_base_fee(800, 10), 800);
+        assert_eq!(market.next_base_fee(800, 20), 900);
+        assert_eq!(market.next_base_fee(800, 15), 850);
+        assert_eq!(market.next_base_fee(800, 0), 700);
+        assert_eq!(market.next_base_fee(0, 0), 0);
+        assert_eq!(market.next_base_fee(0, 11), 1);
+        assert_eq!(market.next_base_fee(Balance::MAX, 20), Balance::MAX);
+    }
+}
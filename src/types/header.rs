@@ -0,0 +1,246 @@
+use crate::traits::{Decode, Encode, Hashable};
+use crate::types::{Balance, DecodeError, Hash, MAX_TARGET_BITS, Timestamp};
+use blake2::digest::FixedOutput;
+use blake2::{Blake2s, Digest};
+
+pub const BLOCK_VERSION: u32 = 1;
+// version, height, prev hash flag and bytes, merkle root, timestamp, bits, base fee, nonce
+pub const HEADER_SIZE: usize = 4 + 8 + 1 + 32 + 32 + 8 + 4 + 16 + 16;
+const NONCE_OFFSET: usize = HEADER_SIZE - 16;
+
+/// Everything the proof-of-work commits to. Transactions are committed through `merkle_root`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockHeader {
+    pub version: u32,
+    pub height: u64,
+    pub prev_hash: Option<Hash>,
+    pub merkle_root: Hash,
+    pub timestamp: Timestamp,
+    // compact form of the target the block hash has to meet
+    pub bits: u32,
+    // burned per transaction, set by the chain from the parent block
+    pub base_fee: Balance,
+    pub nonce: u128,
+}
+
+impl Default for BlockHeader {
+    fn default() -> Self {
+        Self {
+            version: BLOCK_VERSION,
+            height: 0,
+            prev_hash: None,
+            merkle_root: Default::default(),
+            timestamp: 0,
+            bits: MAX_TARGET_BITS,
+            base_fee: 0,
+            nonce: 0,
+        }
+    }
+}
+
+impl BlockHeader {
+    /// Checks that the prev hash and the Merkle root are canonical, so that the proof-of-work
+    /// commits to them as they are stored.
+    pub fn check_hashes(&self) -> Result<(), DecodeError> {
+        if let Some(prev_hash) = &self.prev_hash {
+            hash_to_bytes(prev_hash)?;
+        }
+        hash_to_bytes(&self.merkle_root)?;
+
+        Ok(())
+    }
+
+    /// Fixed-size big-endian encoding which is hashed for the proof-of-work.
+    /// The nonce comes last, so everything before it can be hashed once per block. Hashes which
+    /// fail `check_hashes` are encoded as zeros.
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0u8; HEADER_SIZE];
+        bytes[..4].copy_from_slice(&self.version.to_be_bytes());
+        bytes[4..12].copy_from_slice(&self.height.to_be_bytes());
+        if let Some(prev_hash) = &self.prev_hash {
+            bytes[12] = 1;
+            bytes[13..45].copy_from_slice(&hash_to_bytes(prev_hash).unwrap_or_default());
+        }
+        bytes[45..77].copy_from_slice(&hash_to_bytes(&self.merkle_root).unwrap_or_default());
+        bytes[77..85].copy_from_slice(&self.timestamp.to_be_bytes());
+        bytes[85..89].copy_from_slice(&self.bits.to_be_bytes());
+        bytes[89..NONCE_OFFSET].copy_from_slice(&self.base_fee.to_be_bytes());
+        bytes[NONCE_OFFSET..].copy_from_slice(&self.nonce.to_be_bytes());
+
+        bytes
+    }
+
+    /// Inverse of `to_bytes`, None if the prev hash flag isn't 0 or 1 or a missing prev hash
+    /// isn't all zeros.
+    pub fn from_bytes(bytes: &[u8; HEADER_SIZE]) -> Option<Self> {
+        let prev_hash = match bytes[12] {
+            0 if bytes[13..45].iter().all(|&byte| byte == 0) => None,
+            1 => Some(hex::encode(&bytes[13..45])),
+            _ => return None,
+        };
+
+        let mut version = [0u8; 4];
+        let mut height = [0u8; 8];
+        let mut timestamp = [0u8; 8];
+        let mut bits = [0u8; 4];
+        let mut base_fee = [0u8; 16];
+        let mut nonce = [0u8; 16];
+        version.copy_from_slice(&bytes[..4]);
+        height.copy_from_slice(&bytes[4..12]);
+        timestamp.copy_from_slice(&bytes[77..85]);
+        bits.copy_from_slice(&bytes[85..89]);
+        base_fee.copy_from_slice(&bytes[89..NONCE_OFFSET]);
+        nonce.copy_from_slice(&bytes[NONCE_OFFSET..]);
+
+        Some(Self {
+            version: u32::from_be_bytes(version),
+            height: u64::from_be_bytes(height),
+            prev_hash,
+            merkle_root: hex::encode(&bytes[45..77]),
+            timestamp: u64::from_be_bytes(timestamp),
+            bits: u32::from_be_bytes(bits),
+            base_fee: u128::from_be_bytes(base_fee),
+            nonce: u128::from_be_bytes(nonce),
+        })
+    }
+}
+
+impl Encode for BlockHeader {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_bytes());
+    }
+}
+
+impl Decode for BlockHeader {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        BlockHeader::from_bytes(&Decode::decode_from(input)?).ok_or(DecodeError::InvalidHeader)
+    }
+}
+
+impl Hashable for BlockHeader {
+    fn hash(&self) -> Hash {
+        hex::encode(HeaderHasher::new(self).hash(self.nonce))
+    }
+}
+
+/// Hashes a header for any nonce. Everything but the nonce is absorbed once, so the cost per
+/// nonce doesn't depend on the header content or the size of the block.
+#[derive(Clone)]
+pub struct HeaderHasher {
+    prefix: Blake2s,
+}
+
+impl HeaderHasher {
+    pub fn new(header: &BlockHeader) -> Self {
+        let mut prefix = Blake2s::new();
+        prefix.update(&header.to_bytes()[..NONCE_OFFSET]);
+
+        Self { prefix }
+    }
+
+    pub fn hash(&self, nonce: u128) -> [u8; 32] {
+        let mut hasher = self.prefix.clone();
+        hasher.update(nonce.to_be_bytes());
+
+        hasher.finalize_fixed().into()
+    }
+}
+
+// Bytes of a hash in its canonical form, 64 lowercase hex digits. Any other form is refused,
+// as two strings for the same bytes would share a proof-of-work
+fn hash_to_bytes(hash: &str) -> Result<[u8; 32], DecodeError> {
+    if hash.len() != 64 || hash.bytes().any(|byte| byte.is_ascii_uppercase()) {
+        return Err(DecodeError::InvalidHash);
+    }
+
+    let mut bytes = [0u8; 32];
+    hex::decode_to_slice(hash, &mut bytes).map_err(|_| DecodeError::InvalidHash)?;
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hasher_matches_full_encoding() {
+        let header = BlockHeader {
+            height: 7,
+            prev_hash: Some(hex::encode([3u8; 32])),
+            merkle_root: hex::encode([5u8; 32]),
+            timestamp: 1_600_000_000,
+            nonce: 42,
+            ..Default::default()
+        };
+        let hasher = HeaderHasher::new(&header);
+
+        assert_eq!(hex::encode(hasher.hash(42)), header.hash());
+        assert_eq!(header.hash(), hex::encode(Blake2s::digest(&header.to_bytes())));
+
+        let mut other = header.clone();
+        other.nonce = 43;
+        assert_eq!(hex::encode(hasher.hash(43)), other.hash());
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut header = BlockHeader {
+            height: 7,
+            merkle_root: hex::encode([5u8; 32]),
+            timestamp: 1_600_000_000,
+            base_fee: 3,
+            nonce: 42,
+            ..Default::default()
+        };
+        assert_eq!(BlockHeader::from_bytes(&header.to_bytes()), Some(header.clone()));
+
+        header.prev_hash = Some(hex::encode([3u8; 32]));
+        assert_eq!(BlockHeader::decode(&header.encode()), Ok(header.clone()));
+
+        let mut bytes = header.to_bytes();
+        bytes[12] = 2;
+        assert_eq!(BlockHeader::from_bytes(&bytes), None);
+        bytes[12] = 0;
+        assert_eq!(BlockHeader::from_bytes(&bytes), None);
+    }
+
+    #[test]
+    fn test_every_field_is_committed() {
+        let header = BlockHeader {
+            merkle_root: hex::encode([5u8; 32]),
+            ..Default::default()
+        };
+        let mut changed = vec![header.clone(); 8];
+        changed[0].version += 1;
+        changed[1].height += 1;
+        changed[2].prev_hash = Some(hex::encode([0u8; 32]));
+        changed[3].merkle_root = hex::encode([6u8; 32]);
+        changed[4].timestamp += 1;
+        changed[5].bits += 1;
+        changed[6].nonce += 1;
+        changed[7].base_fee += 1;
+
+        for other in changed {
+            assert_ne!(header.hash(), other.hash());
+        }
+    }
+
+    #[test]
+    fn test_canonical_hashes() {
+        let mut header = BlockHeader {
+            prev_hash: Some(hex::encode([0xabu8; 32])),
+            merkle_root: hex::encode([5u8; 32]),
+            ..Default::default()
+        };
+        assert!(header.check_hashes().is_ok());
+
+        header.prev_hash = Some(hex::encode_upper([0xabu8; 32]));
+        assert_eq!(header.check_hashes(), Err(DecodeError::InvalidHash));
+        header.prev_hash = Some("unknown".to_string());
+        assert_eq!(header.check_hashes(), Err(DecodeError::InvalidHash));
+        header.prev_hash = None;
+        header.merkle_root = hex::encode([5u8; 31]);
+        assert_eq!(header.check_hashes(), Err(DecodeError::InvalidHash));
+    }
+}
@@ -0,0 +1,57 @@
+use crate::types::AccountId;
+use std::collections::HashMap;
+
+/// Previous versions of the entries changed in open batches, so that a batch can be reverted.
+/// Batches nest: the changes of a committed batch are reverted along with the enclosing one.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Journal<V> {
+    // previous entry for every change made since the oldest open batch, None if there wasn't one
+    entries: Vec<(AccountId, Option<V>)>,
+    // entry count at the start of each open batch
+    batches: Vec<usize>,
+}
+
+impl<V> Default for Journal<V> {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            batches: Vec::new(),
+        }
+    }
+}
+
+impl<V: Clone> Journal<V> {
+    pub(crate) fn begin(&mut self) {
+        self.batches.push(self.entries.len());
+    }
+
+    pub(crate) fn commit(&mut self) {
+        self.batches.pop();
+        // an enclosing batch can still revert the changes
+        if self.batches.is_empty() {
+            self.entries.clear();
+        }
+    }
+
+    /// Records the entry `map` holds for `account_id`, before it is changed. Changes made outside
+    /// of a batch aren't recorded.
+    pub(crate) fn record(&mut self, account_id: &str, map: &HashMap<AccountId, V>) {
+        if !self.batches.is_empty() {
+            self.entries.push((account_id.to_string(), map.get(account_id).cloned()));
+        }
+    }
+
+    /// Restores the entries changed in the innermost batch.
+    pub(crate) fn revert(&mut self, map: &mut HashMap<AccountId, V>) {
+        let len = match self.batches.pop() {
+            Some(len) => len,
+            None => return,
+        };
+        for (account_id, previous) in self.entries.drain(len..).rev() {
+            match previous {
+                Some(entry) => map.insert(account_id, entry),
+                None => map.remove(&account_id),
+            };
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::traits::WorldState;
+use crate::types::journal::Journal;
+use crate::types::{Account, AccountId, AccountType, PK, StateError};
+use std::collections::HashMap;
+use std::iter::FromIterator;
+
+/// Accounts held in memory. Batches are journaled, so that they can be reverted.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryState {
+    accounts: HashMap<AccountId, Account>,
+    journal: Journal<Account>,
+}
+
+impl MemoryState {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // Sets an account without journaling it, for undo data which is applied as a whole
+    pub(crate) fn insert_account(&mut self, account_id: AccountId, account: Account) -> Option<Account> {
+        self.accounts.insert(account_id, account)
+    }
+}
+
+impl FromIterator<(AccountId, Account)> for MemoryState {
+    fn from_iter<I: IntoIterator<Item = (AccountId, Account)>>(iter: I) -> Self {
+        Self {
+            accounts: iter.into_iter().collect(),
+            journal: Journal::default(),
+        }
+    }
+}
+
+impl WorldState for MemoryState {
+    fn create_account(
+        &mut self,
+        account_id: AccountId,
+        account_type: AccountType,
+        public_key: PK,
+    ) -> Result<(), StateError> {
+        if self.accounts.contains_key(&account_id) {
+            return Err(StateError::AccountExists(account_id));
+        }
+
+        self.journal.record(&account_id, &self.accounts);
+        self.accounts.insert(account_id, Account::new(account_type, public_key));
+
+        Ok(())
+    }
+
+    fn get_account_by_id(&self, account_id: &str) -> Option<&Account> {
+        self.accounts.get(account_id)
+    }
+
+    fn get_account_by_id_mut(&mut self, account_id: &str) -> Option<&mut Account> {
+        if self.accounts.contains_key(account_id) {
+            self.journal.record(account_id, &self.accounts);
+        }
+        self.accounts.get_mut(account_id)
+    }
+
+    fn remove_account(&mut self, account_id: &str) -> Option<Account> {
+        self.journal.record(account_id, &self.accounts);
+        self.accounts.remove(account_id)
+    }
+
+    fn accounts(&self) -> Box<dyn Iterator<Item = (&AccountId, &Account)> + '_> {
+        Box::new(self.accounts.iter())
+    }
+
+    fn begin_batch(&mut self) {
+        self.journal.begin();
+    }
+
+    fn commit_batch(&mut self) -> Result<(), StateError> {
+        self.journal.commit();
+        Ok(())
+    }
+
+    fn revert_batch(&mut self) {
+        self.journal.revert(&mut self.accounts);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::generate_keypair;
+
+    #[test]
+    fn test_batches() {
+        let public_key = generate_keypair().public;
+        let mut state = MemoryState::new();
+        state.create_account("alice".to_string(), AccountType::User, public_key).unwrap();
+        state.get_account_by_id_mut("alice").unwrap().balance = 10;
+        let before = state.clone();
+
+        state.begin_batch();
+        state.get_account_by_id_mut("alice").unwrap().balance = 3;
+        state.create_account("bob".to_string(), AccountType::User, public_key).unwrap();
+        assert!(state.create_account("bob".to_string(), AccountType::User, public_key).is_err());
+        state.begin_batch();
+        state.remove_account("alice");
+        state.commit_batch().unwrap();
+        assert!(!state.contains_account("alice"));
+
+        // reverts the committed nested batch too
+        state.revert_batch();
+        assert_eq!(state, before);
+
+        // changes outside of a batch stay
+        state.revert_batch();
+        assert_eq!(state.get_account_by_id("alice").map(|account| account.balance), Some(10));
+    }
+}
@@ -0,0 +1,367 @@
+use crate::traits::{Hashable, WorldState};
+use crate::types::{AccountId, Balance, ExecutionContext, Hash, MAX_MEMPOOL_SIZE, MempoolError, StateError, Transaction,
+                   TransactionData, TxError};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+
+// Lower is evicted first: not ready, lower fee, later arrival
+type EvictionKey = (bool, Balance, Reverse<u64>);
+
+#[derive(Debug, Clone)]
+struct PoolEntry {
+    tx: Transaction,
+    hash: Hash,
+    // arrival order, breaks ties between equal fees
+    seq: u64,
+}
+
+impl PoolEntry {
+    // Higher goes first: higher fee, then earlier arrival
+    fn priority(&self) -> (Balance, Reverse<u64>) {
+        (self.tx.fee, Reverse(self.seq))
+    }
+}
+
+/// Transactions waiting to be included in a block.
+///
+/// Transactions are queued per sender by nonce. The ones which continue the sender's nonce
+/// without a gap are ready to be included, the rest wait for the gap to be filled. Across
+/// senders, higher fees go first. When the pool is full, a new transaction evicts the lowest
+/// priority one which can go without leaving a gap, transactions waiting for a gap first.
+#[derive(Debug, Clone)]
+pub struct Mempool {
+    capacity: usize,
+    senders: HashMap<AccountId, BTreeMap<u128, PoolEntry>>,
+    hashes: HashSet<Hash>,
+    next_seq: u64,
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Mempool::new(MAX_MEMPOOL_SIZE)
+    }
+}
+
+impl Mempool {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            senders: HashMap::new(),
+            hashes: HashSet::new(),
+            next_seq: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.hashes.contains(hash)
+    }
+
+    /// Admits a transaction which is well-formed and valid against `state` in a block with the
+    /// given context, except for a nonce ahead of the sender's.
+    pub fn insert<T: WorldState>(&mut self, tx: Transaction, state: &T, context: &ExecutionContext)
+        -> Result<(), MempoolError> {
+        let hash = tx.hash();
+        if self.hashes.contains(&hash) {
+            return Err(MempoolError::AlreadyKnown);
+        }
+        let sender = Mempool::check(&tx, state, context)?;
+
+        if self.senders.get(&sender).is_some_and(|queue| queue.contains_key(&tx.nonce)) {
+            return Err(MempoolError::NonceTaken { sender, nonce: tx.nonce });
+        }
+
+        if self.len() >= self.capacity {
+            let ready = self.ready_nonce(&sender, state) == tx.nonce;
+            let key = (ready, tx.fee, Reverse(self.next_seq));
+            match self.eviction_candidate(&sender, state) {
+                Some((victim_key, victim_sender, victim_nonce)) if victim_key < key => {
+                    self.remove(&victim_sender, victim_nonce)
+                }
+                _ => return Err(MempoolError::Full),
+            }
+        }
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.hashes.insert(hash.clone());
+        self.senders.entry(sender).or_default().insert(tx.nonce, PoolEntry { tx, hash, seq });
+
+        Ok(())
+    }
+
+    /// Transactions which are ready to be included, in the order they should be: by nonce for
+    /// each sender, and by fee, then arrival, across senders.
+    pub fn pending<T: WorldState>(&self, state: &T) -> Vec<&Transaction> {
+        let mut ready: HashMap<&AccountId, Vec<&PoolEntry>> = HashMap::new();
+        let mut heads = BinaryHeap::new();
+        for (sender, queue) in &self.senders {
+            let mut nonce = Mempool::account_nonce(sender, state);
+            let mut entries = Vec::new();
+            while let Some(entry) = queue.get(&nonce) {
+                entries.push(entry);
+                nonce += 1;
+            }
+            entries.reverse();
+            if let Some(head) = entries.last() {
+                heads.push((head.priority(), sender));
+                ready.insert(sender, entries);
+            }
+        }
+
+        let mut pending = Vec::new();
+        while let Some((_, sender)) = heads.pop() {
+            let entries = ready.get_mut(sender).unwrap();
+            pending.push(&entries.pop().unwrap().tx);
+            if let Some(next) = entries.last() {
+                heads.push((next.priority(), sender));
+            }
+        }
+
+        pending
+    }
+
+    /// Drops transactions which were included in a block or can't be executed any more.
+    pub fn prune<T: WorldState>(&mut self, state: &T) {
+        let mut stale = Vec::new();
+        for (sender, queue) in &self.senders {
+            let account_nonce = state.get_account_by_id(sender).map(|account| account.nonce);
+            for (&nonce, entry) in queue {
+                let created = match &entry.tx.data {
+                    TransactionData::CreateAccount(account_id, _) => {
+                        state.contains_account(account_id)
+                    }
+                    _ => false,
+                };
+                if created || matches!(account_nonce, Some(account_nonce) if nonce < account_nonce) {
+                    stale.push((sender.clone(), nonce));
+                }
+            }
+        }
+
+        for (sender, nonce) in stale {
+            self.remove(&sender, nonce);
+        }
+    }
+
+    // Stateless and stateful admission checks, returns the sender
+    fn check<T: WorldState>(tx: &Transaction, state: &T, context: &ExecutionContext) -> Result<AccountId, MempoolError> {
+        let sender = match tx.sender() {
+            Some(sender) => sender.clone(),
+            None => return Err(TxError::MissingSender.into()),
+        };
+        if tx.signature.is_none() {
+            return Err(TxError::MissingSignature.into());
+        }
+
+        let account = state.get_account_by_id(&sender);
+        if let Some(account) = account {
+            if tx.nonce < account.nonce {
+                return Err(TxError::InvalidNonce { nonce: tx.nonce, expected: account.nonce }.into());
+            }
+            if tx.fee < context.base_fee {
+                return Err(TxError::FeeBelowBaseFee { fee: tx.fee, base_fee: context.base_fee }.into());
+            }
+        }
+
+        match &tx.data {
+            TransactionData::CreateAccount(account_id, pub_key) => {
+                if account.is_none() && (account_id != &sender || tx.nonce != 0) {
+                    return Err(TxError::UnknownSender(sender).into());
+                }
+                if account.map_or(0, |account| account.balance) < tx.fee {
+                    return Err(TxError::InsufficientBalance.into());
+                }
+                if state.contains_account(account_id) {
+                    return Err(TxError::State(StateError::AccountExists(account_id.clone())).into());
+                }
+                // an existing sender pays for the account, so it signs
+                let signer_key = account.map_or(pub_key, |account| &account.public_key);
+                tx.verify_signature(signer_key, context.chain_id)?;
+            }
+            TransactionData::MintInitialSupply { .. } => {
+                return Err(TxError::MintOutsideGenesis.into());
+            }
+            TransactionData::Coinbase { .. } => {
+                return Err(MempoolError::Coinbase);
+            }
+            TransactionData::Transfer { amount, .. } => {
+                let account = match account {
+                    Some(account) => account,
+                    None => return Err(TxError::UnknownSender(sender).into()),
+                };
+                tx.verify_signature(&account.public_key, context.chain_id)?;
+                if amount.checked_add(tx.fee).is_none_or(|total| account.balance < total) {
+                    return Err(TxError::InsufficientBalance.into());
+                }
+            }
+        }
+
+        Ok(sender)
+    }
+
+    // A sender which doesn't exist yet can only have its own creation in the pool, at nonce 0
+    fn account_nonce<T: WorldState>(sender: &AccountId, state: &T) -> u128 {
+        state.get_account_by_id(sender).map_or(0, |account| account.nonce)
+    }
+
+    // Nonce which would continue the sender's ready transactions
+    fn ready_nonce<T: WorldState>(&self, sender: &AccountId, state: &T) -> u128 {
+        let mut nonce = Mempool::account_nonce(sender, state);
+        if let Some(queue) = self.senders.get(sender) {
+            while queue.contains_key(&nonce) {
+                nonce += 1;
+            }
+        }
+
+        nonce
+    }
+
+    // Lowest priority transaction of another sender which can be dropped without leaving a gap:
+    // one waiting for a gap, or the last ready one
+    fn eviction_candidate<T: WorldState>(&self, exclude: &AccountId, state: &T)
+        -> Option<(EvictionKey, AccountId, u128)> {
+        self.senders.iter()
+            .filter(|(sender, _)| *sender != exclude)
+            .flat_map(|(sender, queue)| {
+                let ready_nonce = self.ready_nonce(sender, state);
+                let last_ready = ready_nonce.checked_sub(1).and_then(|nonce| queue.get_key_value(&nonce));
+                queue.range(ready_nonce..)
+                    .map(|(nonce, entry)| (false, nonce, entry))
+                    .chain(last_ready.map(|(nonce, entry)| (true, nonce, entry)))
+                    .map(move |(ready, &nonce, entry)| ((ready, entry.tx.fee, Reverse(entry.seq)), sender, nonce))
+            })
+            .min()
+            .map(|(key, sender, nonce)| (key, sender.clone(), nonce))
+    }
+
+    fn remove(&mut self, sender: &AccountId, nonce: u128) {
+        if let Some(queue) = self.senders.get_mut(sender) {
+            if let Some(entry) = queue.remove(&nonce) {
+                self.hashes.remove(&entry.hash);
+            }
+            if queue.is_empty() {
+                self.senders.remove(sender);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{AccountType, MemoryState};
+    use crate::utils::generate_keypair;
+    use ed25519_dalek::Keypair;
+
+    fn funded(state: &mut MemoryState, id: &str) -> Keypair {
+        let keypair = generate_keypair();
+        state.create_account(id.to_string(), AccountType::User, keypair.public).unwrap();
+        state.get_account_by_id_mut(id).unwrap().balance = 100;
+        keypair
+    }
+
+    fn transfer(from: &str, keypair: &Keypair, nonce: u128) -> Transaction {
+        transfer_with_fee(from, keypair, nonce, 0)
+    }
+
+    fn transfer_with_fee(from: &str, keypair: &Keypair, nonce: u128, fee: Balance) -> Transaction {
+        let mut tx = Transaction::new(
+            TransactionData::Transfer { to: "bob".to_string(), amount: 1 }, Some(from.to_string()));
+        tx.nonce = nonce;
+        tx.fee = fee;
+        tx.sign(keypair, 0);
+        tx
+    }
+
+    fn nonces(pending: Vec<&Transaction>) -> Vec<(String, u128)> {
+        pending.iter().map(|tx| (tx.sender().unwrap().clone(), tx.nonce)).collect()
+    }
+
+    #[test]
+    fn test_ordering() {
+        let mut state = MemoryState::new();
+        let alice = funded(&mut state, "alice");
+        let carol = funded(&mut state, "carol");
+        let mut pool = Mempool::default();
+        let context = ExecutionContext::default();
+
+        assert!(pool.insert(transfer("alice", &alice, 1), &state, &context).is_ok());
+        assert!(pool.insert(transfer("carol", &carol, 0), &state, &context).is_ok());
+        assert!(pool.insert(transfer("alice", &alice, 0), &state, &context).is_ok());
+        assert!(pool.insert(transfer("alice", &alice, 3), &state, &context).is_ok());
+        assert!(pool.insert(transfer("carol", &carol, 1), &state, &context).is_ok());
+
+        // alice's nonce 2 is missing, her nonce 3 has to wait
+        let expected = vec![("carol", 0), ("alice", 0), ("alice", 1), ("carol", 1)];
+        let expected: Vec<_> = expected.into_iter().map(|(id, nonce)| (id.to_string(), nonce)).collect();
+        assert_eq!(nonces(pool.pending(&state)), expected);
+
+        state.get_account_by_id_mut("alice").unwrap().nonce = 2;
+        pool.prune(&state);
+        assert_eq!(pool.len(), 3);
+        assert!(pool.insert(transfer("alice", &alice, 2), &state, &context).is_ok());
+        assert_eq!(pool.pending(&state).len(), 4);
+    }
+
+    #[test]
+    fn test_eviction() {
+        let mut state = MemoryState::new();
+        let alice = funded(&mut state, "alice");
+        let carol = funded(&mut state, "carol");
+        let mut pool = Mempool::new(2);
+        let context = ExecutionContext::default();
+
+        assert!(pool.insert(transfer("alice", &alice, 0), &state, &context).is_ok());
+        assert!(pool.insert(transfer("carol", &carol, 5), &state, &context).is_ok());
+
+        // a queued transaction can't get in, a ready one evicts the queued one
+        assert_eq!(pool.insert(transfer("alice", &alice, 2), &state, &context), Err(MempoolError::Full));
+        assert!(pool.insert(transfer("alice", &alice, 1), &state, &context).is_ok());
+        assert_eq!(pool.len(), 2);
+        assert_eq!(nonces(pool.pending(&state)), vec![("alice".to_string(), 0), ("alice".to_string(), 1)]);
+
+        assert!(pool.insert(transfer("carol", &carol, 0), &state, &context).is_err());
+    }
+
+    #[test]
+    fn test_fee_priority() {
+        let mut state = MemoryState::new();
+        let alice = funded(&mut state, "alice");
+        let carol = funded(&mut state, "carol");
+        let dave = funded(&mut state, "dave");
+        let mut pool = Mempool::new(3);
+        let context = ExecutionContext { base_fee: 1, ..Default::default() };
+
+        assert!(pool.insert(transfer_with_fee("alice", &alice, 0, 1), &state, &context).is_ok());
+        assert!(pool.insert(transfer_with_fee("alice", &alice, 1, 9), &state, &context).is_ok());
+        assert!(pool.insert(transfer_with_fee("carol", &carol, 0, 5), &state, &context).is_ok());
+        assert!(pool.insert(transfer_with_fee("carol", &carol, 0, 6), &state, &context).is_err());
+
+        // a sender's nonce order comes first, then fees
+        let expected = vec![("carol", 0), ("alice", 0), ("alice", 1)];
+        let expected: Vec<_> = expected.into_iter().map(|(id, nonce)| (id.to_string(), nonce)).collect();
+        assert_eq!(nonces(pool.pending(&state)), expected);
+
+        // a higher fee evicts the lowest one which doesn't leave a gap
+        assert!(pool.insert(transfer_with_fee("dave", &dave, 0, 5), &state, &context).is_err());
+        assert!(pool.insert(transfer_with_fee("dave", &dave, 0, 6), &state, &context).is_ok());
+        assert_eq!(pool.len(), 3);
+        assert!(!pool.contains(&transfer_with_fee("carol", &carol, 0, 5).hash()));
+
+        assert_eq!(
+            pool.insert(transfer_with_fee("dave", &dave, 1, 0), &state, &context),
+            Err(TxError::FeeBelowBaseFee { fee: 0, base_fee: 1 }.into())
+        );
+
+        // the fee has to be covered too
+        assert!(pool.insert(transfer_with_fee("dave", &dave, 1, 100), &state, &context).is_err());
+    }
+}
@@ -0,0 +1,193 @@
+use crate::types::{Hash, Transaction};
+use blake2::digest::FixedOutput;
+use blake2::{Blake2s, Digest};
+
+// Leaves and inner nodes are hashed with different prefixes, so that an inner node can't be
+// passed off as a leaf
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// Sibling of a node on the path from a leaf to the root.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProofNode {
+    Left(Hash),
+    Right(Hash),
+}
+
+/// Inclusion proof of a leaf: the siblings on the path to the root, lowest level first.
+/// A level where the node has no sibling is carried up as is and doesn't appear in the proof.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MerkleProof {
+    pub nodes: Vec<ProofNode>,
+}
+
+impl MerkleProof {
+    /// Checks that `leaf` is included in the tree with the given `root`.
+    pub fn verify(&self, leaf: &Hash, root: &Hash) -> bool {
+        let mut hash = hash_leaf(leaf);
+        for node in &self.nodes {
+            hash = match node {
+                ProofNode::Left(sibling) => hash_pair(sibling, &hash),
+                ProofNode::Right(sibling) => hash_pair(&hash, sibling),
+            };
+            if hash.is_empty() {
+                return false;
+            }
+        }
+
+        &hash == root
+    }
+}
+
+/// Binary hash tree over a list of leaves. A node without a pair is carried up to the next level.
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    // hashed leaves first, the root level last
+    levels: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    pub fn new(leaves: Vec<Hash>) -> Self {
+        let mut levels = vec![leaves.iter().map(hash_leaf).collect::<Vec<_>>()];
+        while levels.last().unwrap().len() > 1 {
+            let next = levels.last().unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => hash_pair(left, right),
+                    [single] => single.clone(),
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+
+        Self { levels }
+    }
+
+    /// Tree over the full hashes of the transactions, which cover their signatures.
+    pub fn from_transactions(transactions: &[Transaction]) -> Self {
+        MerkleTree::new(transactions.iter().map(Transaction::full_hash).collect())
+    }
+
+    /// Root of the tree, all zeros for a tree without leaves.
+    pub fn root(&self) -> Hash {
+        match self.levels.last().unwrap().first() {
+            Some(root) => root.clone(),
+            None => hex::encode([0u8; 32]),
+        }
+    }
+
+    pub fn proof(&self, mut index: usize) -> Option<MerkleProof> {
+        if index >= self.levels[0].len() {
+            return None;
+        }
+
+        let mut nodes = Vec::new();
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = index ^ 1;
+            if sibling < level.len() {
+                nodes.push(if sibling < index {
+                    ProofNode::Left(level[sibling].clone())
+                } else {
+                    ProofNode::Right(level[sibling].clone())
+                });
+            }
+            index /= 2;
+        }
+
+        Some(MerkleProof { nodes })
+    }
+
+    pub fn proof_for(&self, leaf: &Hash) -> Option<MerkleProof> {
+        let leaf = hash_leaf(leaf);
+        let index = self.levels[0].iter().position(|hash| hash == &leaf)?;
+        self.proof(index)
+    }
+}
+
+fn hash_leaf(leaf: &Hash) -> Hash {
+    hash_prefixed(LEAF_PREFIX, &[leaf])
+}
+
+fn hash_pair(left: &Hash, right: &Hash) -> Hash {
+    hash_prefixed(NODE_PREFIX, &[left, right])
+}
+
+// Empty if one of the hashes isn't valid hex
+fn hash_prefixed(prefix: u8, hashes: &[&Hash]) -> Hash {
+    let mut hasher = Blake2s::new();
+    hasher.update([prefix]);
+    for hash in hashes {
+        match hex::decode(hash) {
+            Ok(bytes) => hasher.update(bytes),
+            Err(_) => return Hash::new(),
+        }
+    }
+
+    hex::encode(hasher.finalize_fixed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(count: usize) -> Vec<Hash> {
+        (0..count)
+            .map(|i| hex::encode(Blake2s::digest(&(i as u64).to_be_bytes())))
+            .collect()
+    }
+
+    #[test]
+    fn test_root() {
+        assert_eq!(MerkleTree::new(vec![]).root(), hex::encode([0u8; 32]));
+
+        let hashes = leaves(3);
+        let leaf = |index: usize| hash_leaf(&hashes[index]);
+        assert_eq!(MerkleTree::new(hashes[..1].to_vec()).root(), leaf(0));
+        assert_eq!(
+            MerkleTree::new(hashes.clone()).root(),
+            hash_pair(&hash_pair(&leaf(0), &leaf(1)), &leaf(2))
+        );
+    }
+
+    #[test]
+    fn test_inner_nodes_are_not_leaves() {
+        let hashes = leaves(3);
+        let tree = MerkleTree::new(hashes.clone());
+        let inner = hash_pair(&hash_leaf(&hashes[0]), &hash_leaf(&hashes[1]));
+
+        // the same tree shape with the inner node as a leaf has another root
+        let forged = MerkleTree::new(vec![inner.clone(), hashes[2].clone()]);
+        assert_ne!(forged.root(), tree.root());
+        assert!(!forged.proof(0).unwrap().verify(&inner, &tree.root()));
+        assert!(!tree.proof(2).unwrap().verify(&inner, &tree.root()));
+    }
+
+    #[test]
+    fn test_proofs() {
+        for count in 1..10 {
+            let hashes = leaves(count);
+            let tree = MerkleTree::new(hashes.clone());
+            let root = tree.root();
+
+            for (index, leaf) in hashes.iter().enumerate() {
+                let proof = tree.proof(index).unwrap();
+                assert!(proof.verify(leaf, &root));
+                assert_eq!(tree.proof_for(leaf), Some(proof));
+            }
+            assert!(tree.proof(count).is_none());
+        }
+    }
+
+    #[test]
+    fn test_invalid_proofs() {
+        let hashes = leaves(5);
+        let tree = MerkleTree::new(hashes.clone());
+        let proof = tree.proof(1).unwrap();
+
+        assert!(!proof.verify(&hashes[2], &tree.root()));
+        assert!(!proof.verify(&hashes[1], &hashes[0]));
+        assert!(!proof.verify(&"zz".to_string(), &tree.root()));
+        assert!(tree.proof_for(&leaves(6)[5]).is_none());
+    }
+}
@@ -2,14 +2,39 @@ mod account;
 mod block;
 mod blockchain;
 mod chain;
+mod difficulty;
+mod encoding;
+mod error;
+mod fee;
+mod header;
+mod journal;
+mod memory;
+mod mempool;
+mod merkle;
+mod overlay;
+mod reward;
+mod target;
 mod transaction;
+mod uint;
+mod undo;
 
 pub use ed25519_dalek::PublicKey;
 pub use account::{Account, AccountType};
-pub use block::Block;
-pub use blockchain::Blockchain;
+pub use block::{Block, BlockLimits};
+pub use blockchain::{Blockchain, BlockStatus};
 pub use chain::Chain;
-pub use transaction::{Transaction, TransactionData};
+pub use difficulty::{BlockTiming, FixedInterval, Lwma, PerBlock};
+pub use error::{BlockError, DecodeError, Error, InvalidBlock, MempoolError, MiningError, StateError, TxError};
+pub use fee::FeeMarket;
+pub use header::{BLOCK_VERSION, BlockHeader, HEADER_SIZE, HeaderHasher};
+pub use memory::MemoryState;
+pub use mempool::Mempool;
+pub use merkle::{MerkleProof, MerkleTree, ProofNode};
+pub use overlay::StateOverlay;
+pub use reward::RewardSchedule;
+pub use target::Target;
+pub use transaction::{ExecutionContext, Transaction, TransactionData};
+pub use uint::U256;
 
 pub type Hash = String;
 pub type Timestamp = u64;
@@ -17,12 +42,37 @@ pub type AccountId = String;
 pub type Balance = u128;
 pub type PK = PublicKey;
 pub type SignatureBytes = [u8; 64];
-pub type Error = String;
-pub type Target = u64;
+pub type Work = U256;
+pub type ChainId = u64;
 
 // for first block
-// 0x00fffff000000000000000000000000000000000000000000000000000000000 => 0x1ffffff0
-pub const MAX_COMPACT_FORM: &'static str = "1ffffff0";
-pub const MAX_TARGET: Target = 536_870_896;
-pub const EXPECTED_TIME: f64 = 1.5;
-pub const COEFFICIENT_LENGTH: usize = 6;
+// 0x00ffff0000000000000000000000000000000000000000000000000000000000 => 0x2000ffff
+pub const MAX_TARGET_BITS: u32 = 0x2000_ffff;
+// milliseconds
+pub const EXPECTED_TIME: u64 = 1_500;
+// a block can't be older than the median timestamp of this many previous blocks
+pub const MEDIAN_TIME_SPAN: usize = 11;
+// seconds a block timestamp can be ahead of the local clock
+pub const MAX_FUTURE_DRIFT: Timestamp = 60;
+// blocks held back until their parent arrives
+pub const MAX_ORPHAN_BLOCKS: usize = 100;
+// seconds a block is held back before it is dropped
+pub const ORPHAN_EXPIRY: Timestamp = 600;
+// prefix of every signed transaction payload, so the signature can't be reused for another message
+pub const TX_SIGNING_DOMAIN: &[u8] = b"blockchain_workshop/transaction";
+// transactions the pool holds by default
+pub const MAX_MEMPOOL_SIZE: usize = 1_000;
+// transactions a block can hold by default, the coinbase included
+pub const MAX_BLOCK_TRANSACTIONS: usize = 1_000;
+// encoded bytes a block can take by default
+pub const MAX_BLOCK_SIZE: usize = 1_000_000;
+// coins the first blocks can create, halved every HALVING_INTERVAL blocks
+pub const INITIAL_SUBSIDY: Balance = 50;
+pub const HALVING_INTERVAL: u64 = 210_000;
+// encoded bytes of transactions paying the base fee a block should carry, more raise the base
+// fee and fewer lower it
+pub const TARGET_BLOCK_SIZE: usize = MAX_BLOCK_SIZE / 2;
+// the base fee changes by at most 1/8 per block
+pub const BASE_FEE_CHANGE_DENOMINATOR: u128 = 8;
+// coins there can ever be by default, minted and coinbase ones alike
+pub const MAX_SUPPLY: Balance = Balance::MAX;
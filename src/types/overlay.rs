@@ -0,0 +1,172 @@
+use crate::traits::WorldState;
+use crate::types::journal::Journal;
+use crate::types::{Account, AccountId, AccountType, PK, StateError};
+use std::collections::HashMap;
+
+/// Buffers account changes on top of a state which stays untouched. An account is copied into
+/// the overlay the first time it is changed, so the cost doesn't depend on the size of the base.
+///
+/// The changes are committed by applying `into_changes` to the base, and discarded by dropping
+/// the overlay. Batches, e.g. one per transaction, can be reverted on their own.
+#[derive(Debug)]
+pub struct StateOverlay<'a, S: WorldState> {
+    base: &'a S,
+    // None for a removed account
+    changes: HashMap<AccountId, Option<Account>>,
+    journal: Journal<Option<Account>>,
+}
+
+impl<'a, S: WorldState> StateOverlay<'a, S> {
+    pub fn new(base: &'a S) -> Self {
+        Self {
+            base,
+            changes: HashMap::new(),
+            journal: Journal::default(),
+        }
+    }
+
+    /// Changed, created and removed (None) accounts, in their latest version.
+    pub fn into_changes(self) -> HashMap<AccountId, Option<Account>> {
+        self.changes
+    }
+}
+
+impl<S: WorldState> WorldState for StateOverlay<'_, S> {
+    fn create_account(
+        &mut self,
+        account_id: AccountId,
+        account_type: AccountType,
+        public_key: PK,
+    ) -> Result<(), StateError> {
+        if self.contains_account(&account_id) {
+            return Err(StateError::AccountExists(account_id));
+        }
+
+        self.journal.record(&account_id, &self.changes);
+        self.changes.insert(account_id, Some(Account::new(account_type, public_key)));
+
+        Ok(())
+    }
+
+    fn get_account_by_id(&self, account_id: &str) -> Option<&Account> {
+        match self.changes.get(account_id) {
+            Some(account) => account.as_ref(),
+            None => self.base.get_account_by_id(account_id),
+        }
+    }
+
+    fn get_account_by_id_mut(&mut self, account_id: &str) -> Option<&mut Account> {
+        if !self.changes.contains_key(account_id) {
+            let account = self.base.get_account_by_id(account_id)?.clone();
+            self.journal.record(account_id, &self.changes);
+            self.changes.insert(account_id.to_string(), Some(account));
+        } else {
+            self.journal.record(account_id, &self.changes);
+        }
+
+        self.changes.get_mut(account_id).and_then(Option::as_mut)
+    }
+
+    fn remove_account(&mut self, account_id: &str) -> Option<Account> {
+        let account = self.get_account_by_id(account_id)?.clone();
+        self.journal.record(account_id, &self.changes);
+        self.changes.insert(account_id.to_string(), None);
+
+        Some(account)
+    }
+
+    fn accounts(&self) -> Box<dyn Iterator<Item = (&AccountId, &Account)> + '_> {
+        let unchanged = self.base.accounts()
+            .filter(move |(account_id, _)| !self.changes.contains_key(*account_id));
+        let changed = self.changes.iter()
+            .filter_map(|(account_id, account)| Some((account_id, account.as_ref()?)));
+
+        Box::new(unchanged.chain(changed))
+    }
+
+    fn begin_batch(&mut self) {
+        self.journal.begin();
+    }
+
+    fn commit_batch(&mut self) -> Result<(), StateError> {
+        self.journal.commit();
+        Ok(())
+    }
+
+    fn revert_batch(&mut self) {
+        self.journal.revert(&mut self.changes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::MemoryState;
+    use crate::utils::generate_keypair;
+
+    fn balance<S: WorldState>(state: &S, account_id: &str) -> Option<u128> {
+        state.get_account_by_id(account_id).map(|account| account.balance)
+    }
+
+    #[test]
+    fn test_overlay() {
+        let public_key = generate_keypair().public;
+        let mut base = MemoryState::new();
+        base.create_account("alice".to_string(), AccountType::User, public_key).unwrap();
+        base.get_account_by_id_mut("alice").unwrap().balance = 10;
+
+        let mut state = StateOverlay::new(&base);
+        state.get_account_by_id_mut("alice").unwrap().balance = 7;
+        state.create_account("bob".to_string(), AccountType::User, public_key).unwrap();
+        assert!(state.create_account("alice".to_string(), AccountType::User, public_key).is_err());
+        assert!(state.get_account_by_id_mut("carol").is_none());
+        assert_eq!((balance(&state, "alice"), balance(&state, "bob")), (Some(7), Some(0)));
+        assert_eq!(balance(&base, "alice"), Some(10));
+
+        let mut ids: Vec<&AccountId> = state.accounts().map(|(account_id, _)| account_id).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["alice", "bob"]);
+
+        assert_eq!(state.remove_account("alice").map(|account| account.balance), Some(7));
+        assert!(!state.contains_account("alice"));
+        assert!(state.get_account_by_id_mut("alice").is_none());
+        assert_eq!(state.accounts().count(), 1);
+        assert!(base.contains_account("alice"));
+
+        let changes = state.into_changes();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes["alice"], None);
+    }
+
+    #[test]
+    fn test_batches() {
+        let public_key = generate_keypair().public;
+        let mut base = MemoryState::new();
+        base.create_account("alice".to_string(), AccountType::User, public_key).unwrap();
+
+        let mut state = StateOverlay::new(&base);
+        state.begin_batch();
+        state.get_account_by_id_mut("alice").unwrap().balance = 1;
+        state.commit_batch().unwrap();
+
+        state.begin_batch();
+        state.get_account_by_id_mut("alice").unwrap().balance = 2;
+        state.create_account("bob".to_string(), AccountType::User, public_key).unwrap();
+
+        // nested in the open batch
+        state.begin_batch();
+        state.get_account_by_id_mut("alice").unwrap().balance = 3;
+        state.revert_batch();
+        assert_eq!(balance(&state, "alice"), Some(2));
+
+        state.begin_batch();
+        state.get_account_by_id_mut("bob").unwrap().balance = 4;
+        state.commit_batch().unwrap();
+        assert_eq!(balance(&state, "bob"), Some(4));
+
+        // reverts the committed nested batch too
+        state.revert_batch();
+        assert_eq!((balance(&state, "alice"), balance(&state, "bob")), (Some(1), None));
+        assert_eq!(state.into_changes().len(), 1);
+    }
+}
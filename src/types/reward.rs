@@ -0,0 +1,49 @@
+use crate::types::{Balance, HALVING_INTERVAL, INITIAL_SUBSIDY};
+
+/// Coins a block can create: `initial_subsidy`, halved every `halving_interval` blocks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RewardSchedule {
+    pub initial_subsidy: Balance,
+    pub halving_interval: u64,
+}
+
+impl Default for RewardSchedule {
+    fn default() -> Self {
+        RewardSchedule::new(INITIAL_SUBSIDY, HALVING_INTERVAL)
+    }
+}
+
+impl RewardSchedule {
+    pub fn new(initial_subsidy: Balance, halving_interval: u64) -> Self {
+        Self {
+            initial_subsidy,
+            halving_interval: halving_interval.max(1),
+        }
+    }
+
+    pub fn subsidy(&self, height: u64) -> Balance {
+        let halvings = height / self.halving_interval;
+        if halvings >= Balance::BITS as u64 {
+            return 0;
+        }
+
+        self.initial_subsidy >> halvings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subsidy() {
+        let schedule = RewardSchedule::new(100, 10);
+        assert_eq!(schedule.subsidy(0), 100);
+        assert_eq!(schedule.subsidy(9), 100);
+        assert_eq!(schedule.subsidy(10), 50);
+        assert_eq!(schedule.subsidy(35), 12);
+        assert_eq!(schedule.subsidy(10 * 7), 0);
+        assert_eq!(schedule.subsidy(u64::MAX), 0);
+        assert_eq!(RewardSchedule::new(100, 0).subsidy(1), 50);
+    }
+}
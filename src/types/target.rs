@@ -0,0 +1,174 @@
+use crate::types::{U256, Work};
+
+const COMPACT_SIGN_BIT: u32 = 0x0080_0000;
+const COMPACT_MANTISSA: u32 = 0x007f_ffff;
+
+/// Proof-of-work target. A block hash, read as a big-endian 256-bit number, meets the target
+/// if it is less than or equal to it.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Target(U256);
+
+impl Target {
+    // 0x00ffff0000000000000000000000000000000000000000000000000000000000
+    pub const MAX: Target = Target(U256::from_limbs([0, 0, 0, 0x00ff_ff00_0000_0000]));
+
+    pub fn new(value: U256) -> Self {
+        Target(value)
+    }
+
+    pub fn as_u256(&self) -> U256 {
+        self.0
+    }
+
+    /// Decodes the compact form (Bitcoin `nBits`): the high byte is the size of the number in
+    /// bytes and the low 3 bytes are its most significant bytes.
+    /// Returns None for negative or overflowing encodings.
+    pub fn from_compact(bits: u32) -> Option<Self> {
+        let size = bits >> 24;
+        let mantissa = bits & COMPACT_MANTISSA;
+
+        if mantissa != 0 && bits & COMPACT_SIGN_BIT != 0 {
+            return None;
+        }
+        if mantissa != 0
+            && (size > 34 || (mantissa > 0xff && size > 33) || (mantissa > 0xffff && size > 32))
+        {
+            return None;
+        }
+
+        let value = if size <= 3 {
+            U256::from_u64((mantissa >> (8 * (3 - size))) as u64)
+        } else {
+            U256::from_u64(mantissa as u64) << (8 * (size - 3))
+        };
+
+        Some(Target(value))
+    }
+
+    /// Encodes the target in compact form, dropping everything below the 3 most significant bytes.
+    pub fn to_compact(&self) -> u32 {
+        let mut size = self.0.bits().div_ceil(8);
+        let mut mantissa = if size <= 3 {
+            (self.0.low_u64() << (8 * (3 - size))) as u32
+        } else {
+            (self.0 >> (8 * (size - 3))).low_u64() as u32
+        };
+
+        // the mantissa is signed, move a set sign bit into the next byte
+        if mantissa & COMPACT_SIGN_BIT != 0 {
+            mantissa >>= 8;
+            size += 1;
+        }
+
+        mantissa | size << 24
+    }
+
+    /// Checks a hex encoded 32-byte hash against the target.
+    pub fn is_met_by(&self, hash: &str) -> bool {
+        matches!(U256::from_hex(hash), Some(hash) if hash <= self.0)
+    }
+
+    pub fn is_met_by_bytes(&self, hash: &[u8; 32]) -> bool {
+        U256::from_be_bytes(*hash) <= self.0
+    }
+
+    /// Expected number of hashes needed to meet the target: 2^256 / (target + 1).
+    pub fn work(&self) -> Work {
+        match self.0.checked_add(U256::ONE) {
+            // 2^256 doesn't fit, but (2^256 - d) / d + 1 == 2^256 / d
+            Some(divisor) => (!self.0).div_rem(divisor).0.saturating_add(U256::ONE),
+            None => U256::ONE,
+        }
+    }
+
+    /// Scales the target by `actual / expected`, limited to a factor of 4 in either direction
+    /// and to `Target::MAX`. The result is rounded to what the compact form can represent.
+    pub fn retarget(&self, actual: u64, expected: u64) -> Target {
+        let expected = expected.max(1);
+        let actual = actual.clamp((expected / 4).max(1), expected.saturating_mul(4));
+
+        // divide first if the product doesn't fit, the precision lost is below the compact form
+        let value = match self.0.checked_mul_u64(actual) {
+            Some(value) => Some(value.div_u64(expected)),
+            None => self.0.div_u64(expected).checked_mul_u64(actual),
+        };
+
+        match value.map(Target) {
+            Some(target) if target <= Target::MAX => Target::from_compact(target.to_compact()).unwrap(),
+            _ => Target::MAX,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::MAX_TARGET_BITS;
+
+    #[test]
+    fn test_max_target() {
+        assert_eq!(Target::from_compact(MAX_TARGET_BITS), Some(Target::MAX));
+        assert_eq!(Target::MAX.to_compact(), MAX_TARGET_BITS);
+        assert_eq!(Target::MAX.work(), U256::from_u64(256));
+    }
+
+    #[test]
+    fn test_compact_round_trip() {
+        for bits in [0x1d00ffffu32, 0x1b0404cb, 0x03123456, 0x04123456, 0x01120000, 0x02123400, 0x2000ffff] {
+            let target = Target::from_compact(bits).unwrap();
+            assert_eq!(target.to_compact(), bits);
+        }
+
+        // Bitcoin's genesis target
+        let target = Target::from_compact(0x1d00ffff).unwrap();
+        assert_eq!(target.as_u256(), U256::from_u64(0xffff) << 208);
+    }
+
+    #[test]
+    fn test_compact_normalization() {
+        assert_eq!(Target::from_compact(0x01003456), Some(Target::default()));
+        assert_eq!(Target::from_compact(0x05001234).unwrap().to_compact(), 0x04123400);
+        assert_eq!(Target::new(U256::from_u64(0x80)).to_compact(), 0x02008000);
+        assert_eq!(Target::default().to_compact(), 0);
+    }
+
+    #[test]
+    fn test_compact_invalid() {
+        assert_eq!(Target::from_compact(0x04923456), None);
+        assert_eq!(Target::from_compact(0xff123456), None);
+        assert_eq!(Target::from_compact(0x22010000), None);
+        assert!(Target::from_compact(0x2100ff00).is_some());
+    }
+
+    #[test]
+    fn test_is_met_by() {
+        let target = Target::from_compact(0x1f00ffff).unwrap();
+        let below = "0000ff0000000000000000000000000000000000000000000000000000000000";
+        let equal = "0000ffff00000000000000000000000000000000000000000000000000000000";
+        let above = "0000ffff00000000000000000000000000000000000000000000000000000001";
+        assert!(target.is_met_by(below));
+        assert!(target.is_met_by(equal));
+        assert!(!target.is_met_by(above));
+        assert!(!target.is_met_by("not a hash"));
+    }
+
+    #[test]
+    fn test_retarget() {
+        let target = Target::from_compact(0x1d00ffff).unwrap();
+
+        assert_eq!(target.retarget(1500, 1500), target);
+        assert_eq!(target.retarget(3000, 1500).to_compact(), 0x1d01fffe);
+        assert_eq!(target.retarget(0, 1500).to_compact(), 0x1c3fffc0);
+        assert_eq!(target.retarget(1_000_000, 1500).to_compact(), 0x1d03fffc);
+        assert_eq!(Target::MAX.retarget(3000, 1500), Target::MAX);
+        assert_eq!(Target::MAX.retarget(1 << 40, 1 << 41), Target::from_compact(0x1f7fff80).unwrap());
+    }
+
+    #[test]
+    fn test_work() {
+        let easy = Target::from_compact(0x1f00ffff).unwrap();
+        let hard = Target::from_compact(0x1e00ffff).unwrap();
+        assert!(hard.work() > easy.work());
+        assert_eq!(Target::new(U256::MAX).work(), U256::ONE);
+    }
+}
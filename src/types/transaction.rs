@@ -1,30 +1,54 @@
-use crate::traits::{Hashable, WorldState};
-use crate::types::{AccountId, AccountType, Balance, Error, Hash, PK, SignatureBytes, Timestamp};
-use blake2::digest::FixedOutput;
+use crate::traits::{Decode, Encode, Hashable, WorldState};
+use crate::types::encoding::decode_tag;
+use crate::types::{Account, AccountId, AccountType, Balance, ChainId, DecodeError, Hash, PK, SignatureBytes, StateError,
+                   Timestamp, TX_SIGNING_DOMAIN, TxError};
 use blake2::{Blake2s, Digest};
-use ed25519_dalek::{Signature, Verifier};
+use ed25519_dalek::{Keypair, Signature, Signer, Verifier};
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Transaction {
-    nonce: u128,
+    // has to match the sender's account nonce
+    pub nonce: u128,
+    // paid by the sender on top of the amount: the block's base fee is burned, the rest goes
+    // to the block's miner
+    pub fee: Balance,
     timestamp: Timestamp,
     from: Option<AccountId>,
     pub data: TransactionData,
     pub signature: Option<SignatureBytes>,
 }
 
-#[derive(Debug, Clone)]
+/// What the execution of a transaction depends on besides the world state.
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionContext {
+    // chain the signatures have to be made for
+    pub chain_id: ChainId,
+    pub is_genesis: bool,
+    pub height: u64,
+    // most a coinbase can claim
+    pub subsidy: Balance,
+    // part of every fee which is burned
+    pub base_fee: Balance,
+    // account the block's coinbase pays, None in the genesis block, whose fees are burned
+    pub miner: Option<AccountId>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum TransactionData {
     CreateAccount(AccountId, PK),
     MintInitialSupply { to: AccountId, amount: Balance },
     Transfer { to: AccountId, amount: Balance },
+    // block reward, only valid as the first transaction of a block. The height makes the hash
+    // of every coinbase unique.
+    Coinbase { to: AccountId, amount: Balance, height: u64 },
 }
 
 impl Transaction {
     pub fn new(data: TransactionData, from: Option<AccountId>) -> Self {
         Self {
             nonce: 0,
+            fee: 0,
             timestamp: 0,
             from,
             data,
@@ -32,100 +56,260 @@ impl Transaction {
         }
     }
 
-    pub fn execute<T: WorldState>(&self, state: &mut T, is_genesis: bool) -> Result<(), Error> {
+    /// Bytes the sender signs: a domain tag, the chain ID and the encoded transaction without
+    /// the signature, so that the signature can't be replayed on another chain or passed off as
+    /// another kind of message.
+    pub fn signing_payload(&self, chain_id: ChainId) -> Vec<u8> {
+        let mut payload = TX_SIGNING_DOMAIN.to_vec();
+        chain_id.encode_to(&mut payload);
+        self.encode_unsigned(&mut payload);
+
+        payload
+    }
+
+    /// Hash of the whole encoding, signature included. Blocks commit to their transactions
+    /// through it, so that a signature can't be swapped without changing the block hash.
+    pub fn full_hash(&self) -> Hash {
+        hex::encode(Blake2s::digest(&self.encode()))
+    }
+
+    pub fn is_coinbase(&self) -> bool {
+        matches!(self.data, TransactionData::Coinbase { .. })
+    }
+
+    /// Whether the transaction pays the base fee, and so counts toward the block's fullness:
+    /// mints, coinbases and accounts created by themselves have nothing to pay it with.
+    pub fn pays_base_fee(&self) -> bool {
+        match &self.data {
+            TransactionData::CreateAccount(account_id, _) => self.from.as_ref() != Some(account_id),
+            TransactionData::Transfer { .. } => true,
+            TransactionData::MintInitialSupply { .. } | TransactionData::Coinbase { .. } => false,
+        }
+    }
+
+    pub fn sender(&self) -> Option<&AccountId> {
+        self.from.as_ref()
+    }
+
+    /// Checks the signature against `public_key` for the chain with the given ID. A signature
+    /// made for another chain doesn't verify, the chain ID is part of the payload.
+    pub fn verify_signature(&self, public_key: &PK, chain_id: ChainId) -> Result<(), TxError> {
+        let signature = self.signature.ok_or(TxError::MissingSignature)?;
+        public_key.verify(&self.signing_payload(chain_id), &Signature::from(signature))
+            .map_err(|_| TxError::InvalidSignature)
+    }
+
+    /// Signs the transaction for the chain with the given ID.
+    pub fn sign(&mut self, keypair: &Keypair, chain_id: ChainId) {
+        self.signature = Some(keypair.sign(&self.signing_payload(chain_id)).to_bytes());
+    }
+
+    /// Executes the transaction in a batch of its own, which is reverted if it fails.
+    pub fn execute<T: WorldState>(&self, state: &mut T, context: &ExecutionContext) -> Result<(), TxError> {
+        state.begin_batch();
+        match self.apply(state, context) {
+            Ok(()) => state.commit_batch().map_err(TxError::from),
+            Err(error) => {
+                state.revert_batch();
+                Err(error)
+            }
+        }
+    }
+
+    fn apply<T: WorldState>(&self, state: &mut T, context: &ExecutionContext) -> Result<(), TxError> {
+        if self.from.is_none() && self.fee != 0 {
+            return Err(TxError::UnexpectedFee);
+        }
+        // nobody pays for a mint or a coinbase, so it can't carry a sender
+        if self.is_issuance() && self.from.is_some() {
+            return Err(TxError::UnexpectedSender);
+        }
 
         match &self.data {
 
             TransactionData::CreateAccount(account_id, pub_key) => {
-                Transaction::create_account(&self, state, account_id, pub_key)
+                Transaction::create_account(self, state, account_id, pub_key, context)
             }
 
             TransactionData::MintInitialSupply { to, amount } => {
-                Transaction::mint_init_supply(&self, state, to, amount, is_genesis)
+                Transaction::mint_init_supply(self, state, to, amount, context.is_genesis)
             }
 
             TransactionData::Transfer { to, amount } => {
-                Transaction::transfer(&self, state, to, amount)
+                Transaction::transfer(self, state, to, amount, context)
+            }
+
+            TransactionData::Coinbase { to, amount, height } => {
+                Transaction::coinbase(self, state, to, amount, *height, context)
             }
         }
     }
 
-    fn create_account<T: WorldState>(&self, state: &mut T, account_id: &AccountId, pub_key: &PK) -> Result<(), Error> {
+    fn create_account<T: WorldState>(&self, state: &mut T, account_id: &AccountId, pub_key: &PK,
+                                     context: &ExecutionContext) -> Result<(), TxError> {
         if self.from.is_none() {
-            return Err("Sender name doesn't exist!".to_string());
+            return Err(TxError::MissingSender);
         }
 
         let sender_id = self.from.clone().unwrap();
-        let sender_acc = state.get_account_by_id(sender_id.clone());
+        let sender_acc = state.get_account_by_id(&sender_id);
 
         // if sender account is created by itself
         // or sender account already exist: verify signature
         if (sender_acc.is_none()) && (&sender_id != account_id) {
-            return Err("Creating account by other non-existent account!".to_string());
+            return Err(TxError::UnknownSender(sender_id));
         }
 
-        let res = Transaction::check_tx_create_sign(self, *pub_key, self.signature.clone());
-        if let Err(error) = res {
-            return Err(format!("Error during tx execution: {}", error));
+        // an existing sender pays for the account, so it signs; a new account signs for itself.
+        // Checked first, nobody else can spend the sender's nonce.
+        let signer_key = sender_acc.map_or(*pub_key, |sender_acc| sender_acc.public_key);
+        self.verify_signature(&signer_key, context.chain_id)?;
+
+        // an account created by itself doesn't have a nonce to use yet, nor money for a fee
+        let sender_exists = sender_acc.is_some();
+        if let Some(sender_acc) = sender_acc {
+            Transaction::check_nonce(self, sender_acc)?;
+            Transaction::check_fee(self, context)?;
+        }
+        if !Transaction::is_enough(&sender_acc.map_or(0, |sender_acc| sender_acc.balance), &self.fee) {
+            return Err(TxError::InsufficientBalance);
         }
 
-        state.create_account(account_id.clone(), AccountType::User, *pub_key)
+        state.create_account(account_id.clone(), AccountType::User, *pub_key)?;
+        if sender_exists {
+            Transaction::debit(state, &sender_id, self.fee)?;
+            state.get_account_by_id_mut(&sender_id).unwrap().nonce += 1;
+            Transaction::pay_fee(self, state, context)?;
+        }
+
+        Ok(())
     }
 
-    fn mint_init_supply<T: WorldState>(&self, state: &mut T, to: &AccountId, amount: &Balance, is_genesis: bool) -> Result<(), Error>
+    fn mint_init_supply<T: WorldState>(&self, state: &mut T, to: &AccountId, amount: &Balance, is_genesis: bool) -> Result<(), TxError>
     {
         if !is_genesis {
-            return Err("Initial supply can be minted only in genesis block.".to_string());
+            return Err(TxError::MintOutsideGenesis);
         }
-        match state.get_account_by_id_mut(to.clone()) {
-            Some(account) => {
-                account.balance += amount;
-                Ok(())
-            }
-            None => Err("Invalid account.".to_string()),
+
+        Transaction::credit(state, to, *amount)
+    }
+
+    fn coinbase<T: WorldState>(&self, state: &mut T, to: &AccountId, amount: &Balance, height: u64,
+                               context: &ExecutionContext) -> Result<(), TxError> {
+        if height != context.height {
+            return Err(TxError::InvalidCoinbaseHeight { height, expected: context.height });
+        }
+        if *amount > context.subsidy {
+            return Err(TxError::ExcessiveCoinbase { amount: *amount, subsidy: context.subsidy });
         }
+
+        Transaction::credit(state, to, *amount)
     }
 
-    fn transfer<T: WorldState>(&self, state: &mut T, to: &AccountId, amount: &Balance) -> Result<(), Error> {
+    fn transfer<T: WorldState>(&self, state: &mut T, to: &AccountId, amount: &Balance,
+                               context: &ExecutionContext) -> Result<(), TxError> {
         // Taking Sender's &AccountId
         let sender;
         let sender_account = match &self.from {
             Some(tmp) => {
                 sender = tmp;
-                state.get_account_by_id(tmp.clone())
+                state.get_account_by_id(tmp)
             },
-            None => { return Err("Sender name doesn't exist".to_string()); }
+            None => { return Err(TxError::MissingSender); }
         };
 
         // If sender account exist
-        if sender_account.is_none() {
-            return Err("Sender account doesn't exist".to_string())
-        } else if self.signature.is_none() {
-            return Err("Signature doesn't exist!".to_string());
+        let sender_account = match sender_account {
+            Some(sender_account) => sender_account,
+            None => return Err(TxError::UnknownSender(sender.clone())),
+        };
+
+        self.verify_signature(&sender_account.public_key, context.chain_id)?;
+
+        Transaction::check_nonce(self, sender_account)?;
+        Transaction::check_fee(self, context)?;
+
+        let total = match amount.checked_add(self.fee) {
+            Some(total) => total,
+            None => return Err(TxError::InsufficientBalance),
+        };
+
+        // Check sender's balance
+        if !Transaction::is_enough(&sender_account.balance, &total) {
+            return Err(TxError::InsufficientBalance);
+        }
+        if !state.contains_account(to) {
+            return Err(TxError::UnknownAccount(to.clone()));
         }
 
-        // If signature is true
-        let sender_account = sender_account.unwrap();
-        let signature_presence = Transaction::check_tx_sign(
-            &self, sender_account.public_key.clone(), self.signature.clone());
+        // the sender pays first, so that a transfer to itself can't overflow
+        Transaction::debit(state, sender, total)?;
+        state.get_account_by_id_mut(sender).unwrap().nonce += 1;
+        Transaction::credit(state, to, *amount)?;
+        Transaction::pay_fee(self, state, context)
+    }
+
+    fn credit<T: WorldState>(state: &mut T, account_id: &AccountId, amount: Balance) -> Result<(), TxError> {
+        let account = state.get_account_by_id_mut(account_id)
+            .ok_or_else(|| TxError::UnknownAccount(account_id.clone()))?;
+        account.balance = account.balance.checked_add(amount)
+            .ok_or_else(|| StateError::BalanceOverflow(account_id.clone()))?;
+
+        Ok(())
+    }
+
+    fn debit<T: WorldState>(state: &mut T, account_id: &AccountId, amount: Balance) -> Result<(), TxError> {
+        let account = state.get_account_by_id_mut(account_id)
+            .ok_or_else(|| TxError::UnknownAccount(account_id.clone()))?;
+        account.balance = account.balance.checked_sub(amount).ok_or(TxError::InsufficientBalance)?;
 
-        if signature_presence == false {
-            return  Err("Verify signature error!".to_string());
+        Ok(())
+    }
+
+    fn check_fee(&self, context: &ExecutionContext) -> Result<(), TxError> {
+        if self.fee < context.base_fee {
+            return Err(TxError::FeeBelowBaseFee { fee: self.fee, base_fee: context.base_fee });
         }
 
-        // Check sender's balance
-        if Transaction::is_enough(&sender_account.balance, amount) {
-            // If receiver account exist, send money
-            match state.get_account_by_id_mut(to.clone()) {
-                Some(receiver) => {
-                    receiver.balance += amount;
-                    let sender_account = (state.get_account_by_id_mut(sender.clone())).unwrap();
-                    sender_account.balance -= amount;
-                    return Ok(());
-                },
-                None => { return Err("Receiver doesn't exist".to_string()) }
-            }
-        } else { return Err("Sender haven't enough money!".to_string()); }
+        Ok(())
+    }
+
+    // The base fee is burned and the tip goes to the miner. The miner's account exists, the
+    // coinbase paying it was executed first.
+    fn pay_fee<T: WorldState>(&self, state: &mut T, context: &ExecutionContext) -> Result<(), TxError> {
+        match &context.miner {
+            Some(miner) => Transaction::credit(state, miner, self.fee - context.base_fee),
+            None => Ok(()),
+        }
+    }
+
+    /// Coins the transaction creates and destroys: a mint or a coinbase creates its amount, and
+    /// the part of the fee debited from the sender which doesn't go to a miner is burned.
+    pub fn supply_change(&self, context: &ExecutionContext) -> (Balance, Balance) {
+        let (issued, paid) = match &self.data {
+            TransactionData::MintInitialSupply { amount, .. } | TransactionData::Coinbase { amount, .. } => (*amount, 0),
+            _ => (0, self.fee),
+        };
+        let burned = match context.miner {
+            Some(_) => paid.min(context.base_fee),
+            None => paid,
+        };
+
+        (issued, burned)
+    }
+
+    // A mint or a coinbase creates coins instead of moving them
+    fn is_issuance(&self) -> bool {
+        matches!(self.data, TransactionData::MintInitialSupply { .. } | TransactionData::Coinbase { .. })
+    }
+
+    // Replay protection: a transaction can only be executed with the sender's current nonce
+    fn check_nonce(&self, sender: &Account) -> Result<(), TxError> {
+        if self.nonce != sender.nonce {
+            return Err(TxError::InvalidNonce { nonce: self.nonce, expected: sender.nonce });
+        }
+
+        Ok(())
     }
 
     // Chek sender's balance
@@ -134,42 +318,93 @@ impl Transaction {
 
         false
     }
+}
 
-    fn check_tx_create_sign(&self, pub_key: PK, signature: Option<SignatureBytes>) -> Result<(), Error> {
-        if signature.is_none() {
-            return Err("Signature doesn't exist!".to_string());
-        }
+impl Transaction {
+    // Everything but the signature, in encoding order
+    fn encode_unsigned(&self, out: &mut Vec<u8>) {
+        self.nonce.encode_to(out);
+        self.fee.encode_to(out);
+        self.timestamp.encode_to(out);
+        self.from.encode_to(out);
+        self.data.encode_to(out);
+    }
+}
 
-        let verification = pub_key
-            .verify(self.hash().as_bytes(), &Signature::from(signature.unwrap())).is_ok();
+impl Encode for Transaction {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.encode_unsigned(out);
+        self.signature.encode_to(out);
+    }
+}
 
-        if verification {
-            return Ok(());
-        }
+impl Decode for Transaction {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            nonce: Decode::decode_from(input)?,
+            fee: Decode::decode_from(input)?,
+            timestamp: Decode::decode_from(input)?,
+            from: Decode::decode_from(input)?,
+            data: Decode::decode_from(input)?,
+            signature: Decode::decode_from(input)?,
+        })
+    }
+}
 
-        return Err("Verify signature error!".to_string())
+impl Encode for TransactionData {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        match self {
+            TransactionData::CreateAccount(account_id, pub_key) => {
+                0u8.encode_to(out);
+                account_id.encode_to(out);
+                pub_key.encode_to(out);
+            }
+            TransactionData::MintInitialSupply { to, amount } => {
+                1u8.encode_to(out);
+                to.encode_to(out);
+                amount.encode_to(out);
+            }
+            TransactionData::Transfer { to, amount } => {
+                2u8.encode_to(out);
+                to.encode_to(out);
+                amount.encode_to(out);
+            }
+            TransactionData::Coinbase { to, amount, height } => {
+                3u8.encode_to(out);
+                to.encode_to(out);
+                amount.encode_to(out);
+                height.encode_to(out);
+            }
+        }
     }
+}
 
-    fn check_tx_sign(&self, pub_key: PK, signature: Option<SignatureBytes>) -> bool {
-        return pub_key
-            .verify(self.hash().as_bytes(), &Signature::from(signature.unwrap())).is_ok()
+impl Decode for TransactionData {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match decode_tag(input, 4)? {
+            0 => Ok(TransactionData::CreateAccount(Decode::decode_from(input)?, Decode::decode_from(input)?)),
+            1 => Ok(TransactionData::MintInitialSupply {
+                to: Decode::decode_from(input)?,
+                amount: Decode::decode_from(input)?,
+            }),
+            2 => Ok(TransactionData::Transfer {
+                to: Decode::decode_from(input)?,
+                amount: Decode::decode_from(input)?,
+            }),
+            _ => Ok(TransactionData::Coinbase {
+                to: Decode::decode_from(input)?,
+                amount: Decode::decode_from(input)?,
+                height: Decode::decode_from(input)?,
+            }),
+        }
     }
 }
 
 impl Hashable for Transaction {
     fn hash(&self) -> Hash {
-        let mut hasher = Blake2s::new();
-
-        hasher.update(format!(
-            "{:?}",
-            (
-                self.nonce,
-                self.timestamp,
-                self.from.clone(),
-                self.data.clone()
-            )
-        ));
-
-        hex::encode(hasher.finalize_fixed())
+        let mut bytes = Vec::new();
+        self.encode_unsigned(&mut bytes);
+
+        hex::encode(Blake2s::digest(&bytes))
     }
 }
@@ -0,0 +1,265 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Not, Shl, Shr};
+
+/// Unsigned 256-bit integer, stored as four 64-bit limbs, least significant first.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct U256([u64; 4]);
+
+impl U256 {
+    pub const ZERO: U256 = U256([0; 4]);
+    pub const ONE: U256 = U256([1, 0, 0, 0]);
+    pub const MAX: U256 = U256([u64::MAX; 4]);
+
+    pub const fn from_limbs(limbs: [u64; 4]) -> Self {
+        U256(limbs)
+    }
+
+    pub fn from_u64(value: u64) -> Self {
+        U256([value, 0, 0, 0])
+    }
+
+    pub fn from_be_bytes(bytes: [u8; 32]) -> Self {
+        let mut limbs = [0u64; 4];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let mut chunk = [0u8; 8];
+            chunk.copy_from_slice(&bytes[32 - 8 * (i + 1)..32 - 8 * i]);
+            *limb = u64::from_be_bytes(chunk);
+        }
+
+        U256(limbs)
+    }
+
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (i, limb) in self.0.iter().enumerate() {
+            bytes[32 - 8 * (i + 1)..32 - 8 * i].copy_from_slice(&limb.to_be_bytes());
+        }
+
+        bytes
+    }
+
+    /// Parses a big-endian hex string of exactly 32 bytes, e.g. a block hash.
+    pub fn from_hex(hex_str: &str) -> Option<Self> {
+        let bytes = hex::decode(hex_str).ok()?;
+        if bytes.len() != 32 {
+            return None;
+        }
+
+        let mut be_bytes = [0u8; 32];
+        be_bytes.copy_from_slice(&bytes);
+        Some(U256::from_be_bytes(be_bytes))
+    }
+
+    /// Lowest 64 bits of the value.
+    pub fn low_u64(&self) -> u64 {
+        self.0[0]
+    }
+
+    /// Number of significant bits.
+    pub fn bits(&self) -> u32 {
+        for i in (0..4).rev() {
+            if self.0[i] != 0 {
+                return 64 * i as u32 + (64 - self.0[i].leading_zeros());
+            }
+        }
+
+        0
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == [0; 4]
+    }
+
+    pub fn checked_add(&self, other: U256) -> Option<U256> {
+        let mut result = [0u64; 4];
+        let mut carry = false;
+        for (i, limb) in result.iter_mut().enumerate() {
+            let (sum, carry1) = self.0[i].overflowing_add(other.0[i]);
+            let (sum, carry2) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = carry1 || carry2;
+        }
+
+        if carry { None } else { Some(U256(result)) }
+    }
+
+    pub fn saturating_add(&self, other: U256) -> U256 {
+        self.checked_add(other).unwrap_or(U256::MAX)
+    }
+
+    pub fn checked_sub(&self, other: U256) -> Option<U256> {
+        let mut result = [0u64; 4];
+        let mut borrow = false;
+        for (i, limb) in result.iter_mut().enumerate() {
+            let (diff, borrow1) = self.0[i].overflowing_sub(other.0[i]);
+            let (diff, borrow2) = diff.overflowing_sub(borrow as u64);
+            *limb = diff;
+            borrow = borrow1 || borrow2;
+        }
+
+        if borrow { None } else { Some(U256(result)) }
+    }
+
+    pub fn checked_mul_u64(&self, other: u64) -> Option<U256> {
+        let mut result = [0u64; 4];
+        let mut carry: u128 = 0;
+        for (i, limb) in result.iter_mut().enumerate() {
+            let product = self.0[i] as u128 * other as u128 + carry;
+            *limb = product as u64;
+            carry = product >> 64;
+        }
+
+        if carry != 0 { None } else { Some(U256(result)) }
+    }
+
+    pub fn div_u64(&self, other: u64) -> U256 {
+        assert!(other != 0, "division by zero");
+
+        let mut result = [0u64; 4];
+        let mut remainder: u128 = 0;
+        for i in (0..4).rev() {
+            let dividend = (remainder << 64) | self.0[i] as u128;
+            result[i] = (dividend / other as u128) as u64;
+            remainder = dividend % other as u128;
+        }
+
+        U256(result)
+    }
+
+    /// Long division, returns the quotient and the remainder.
+    pub fn div_rem(&self, other: U256) -> (U256, U256) {
+        assert!(!other.is_zero(), "division by zero");
+
+        let mut quotient = U256::ZERO;
+        let mut remainder = U256::ZERO;
+        for bit in (0..self.bits()).rev() {
+            remainder = remainder << 1;
+            remainder.0[0] |= (self.0[(bit / 64) as usize] >> (bit % 64)) & 1;
+            if remainder >= other {
+                remainder = remainder.checked_sub(other).unwrap();
+                quotient.0[(bit / 64) as usize] |= 1 << (bit % 64);
+            }
+        }
+
+        (quotient, remainder)
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.iter().rev().cmp(other.0.iter().rev())
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Not for U256 {
+    type Output = U256;
+
+    fn not(self) -> U256 {
+        U256([!self.0[0], !self.0[1], !self.0[2], !self.0[3]])
+    }
+}
+
+impl Shl<u32> for U256 {
+    type Output = U256;
+
+    fn shl(self, shift: u32) -> U256 {
+        let mut result = [0u64; 4];
+        let (limbs, bits) = ((shift / 64) as usize, shift % 64);
+        for (i, limb) in result.iter_mut().enumerate().skip(limbs) {
+            *limb = self.0[i - limbs] << bits;
+            if bits > 0 && i > limbs {
+                *limb |= self.0[i - limbs - 1] >> (64 - bits);
+            }
+        }
+
+        U256(result)
+    }
+}
+
+impl Shr<u32> for U256 {
+    type Output = U256;
+
+    fn shr(self, shift: u32) -> U256 {
+        let mut result = [0u64; 4];
+        let (limbs, bits) = ((shift / 64) as usize, shift % 64);
+        for (i, limb) in result.iter_mut().enumerate().take(4usize.saturating_sub(limbs)) {
+            *limb = self.0[i + limbs] >> bits;
+            if bits > 0 && i + limbs + 1 < 4 {
+                *limb |= self.0[i + limbs + 1] << (64 - bits);
+            }
+        }
+
+        U256(result)
+    }
+}
+
+impl fmt::LowerHex for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.to_be_bytes()))
+    }
+}
+
+impl fmt::Debug for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:x}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut bytes = [0u8; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+
+        let value = U256::from_be_bytes(bytes);
+        assert_eq!(value.to_be_bytes(), bytes);
+        assert_eq!(value.low_u64(), 0x18191a1b1c1d1e1f);
+        assert_eq!(U256::from_hex(&hex::encode(bytes)), Some(value));
+    }
+
+    #[test]
+    fn test_shifts_and_bits() {
+        let one = U256::ONE;
+        assert_eq!((one << 255).bits(), 256);
+        assert_eq!((one << 255) >> 255, one);
+        assert_eq!((one << 70) >> 6, one << 64);
+        assert_eq!(one << 256, U256::ZERO);
+        assert_eq!(U256::ZERO.bits(), 0);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = U256::from_u64(u64::MAX);
+        assert_eq!(a.checked_add(U256::ONE), Some(U256::ONE << 64));
+        assert_eq!(U256::MAX.checked_add(U256::ONE), None);
+        assert_eq!((U256::ONE << 64).checked_sub(U256::ONE), Some(a));
+        assert_eq!(U256::ZERO.checked_sub(U256::ONE), None);
+        assert_eq!(a.checked_mul_u64(2), Some((U256::ONE << 65).checked_sub(U256::from_u64(2)).unwrap()));
+        assert_eq!(U256::MAX.checked_mul_u64(2), None);
+        assert_eq!((U256::ONE << 100).div_u64(1 << 36), U256::ONE << 64);
+
+        let (quotient, remainder) = (U256::ONE << 200).checked_add(U256::from_u64(5)).unwrap()
+            .div_rem(U256::ONE << 100);
+        assert_eq!(quotient, U256::ONE << 100);
+        assert_eq!(remainder, U256::from_u64(5));
+    }
+
+    #[test]
+    fn test_ordering() {
+        assert!(U256::ONE << 64 > U256::from_u64(u64::MAX));
+        assert!(U256::ZERO < U256::ONE);
+        assert_eq!(!U256::ZERO, U256::MAX);
+    }
+}
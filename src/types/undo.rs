@@ -0,0 +1,77 @@
+use crate::traits::WorldState;
+use crate::types::{Account, AccountId, Balance, MemoryState};
+use std::collections::HashMap;
+
+/// What a block changed, enough to restore the state from before it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BlockUndo {
+    // previous version of every account the block touched, None for the ones it created
+    accounts: HashMap<AccountId, Option<Account>>,
+    total_supply: Balance,
+}
+
+impl BlockUndo {
+    /// Commits the account changes of a block, None removing the account, and records the
+    /// versions they replace.
+    pub(crate) fn apply(
+        changes: HashMap<AccountId, Option<Account>>,
+        accounts: &mut MemoryState,
+        total_supply: Balance,
+    ) -> Self {
+        let accounts = changes.into_iter()
+            .map(|(account_id, account)| {
+                let previous = match account {
+                    Some(account) => accounts.insert_account(account_id.clone(), account),
+                    None => accounts.remove_account(&account_id),
+                };
+                (account_id, previous)
+            })
+            .collect();
+
+        Self { accounts, total_supply }
+    }
+
+    /// Restores the accounts, returns the total supply from before the block.
+    pub(crate) fn revert(self, accounts: &mut MemoryState) -> Balance {
+        for (account_id, account) in self.accounts {
+            match account {
+                Some(account) => accounts.insert_account(account_id, account),
+                None => accounts.remove_account(&account_id),
+            };
+        }
+
+        self.total_supply
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{AccountType, StateOverlay};
+    use crate::utils::generate_keypair;
+
+    #[test]
+    fn test_revert() {
+        let mut accounts = MemoryState::new();
+        let public_key = generate_keypair().public;
+        accounts.create_account("alice".to_string(), AccountType::User, public_key).unwrap();
+        accounts.get_account_by_id_mut("alice").unwrap().balance = 10;
+        let before = accounts.clone();
+
+        let mut state = StateOverlay::new(&accounts);
+        state.get_account_by_id_mut("alice").unwrap().balance = 3;
+        state.get_account_by_id_mut("alice").unwrap().balance = 5;
+        state.create_account("bob".to_string(), AccountType::User, public_key).unwrap();
+        state.get_account_by_id_mut("bob").unwrap().balance = 5;
+        state.create_account("carol".to_string(), AccountType::User, public_key).unwrap();
+        state.remove_account("carol");
+        let changes = state.into_changes();
+
+        let undo = BlockUndo::apply(changes, &mut accounts, 10);
+        assert_eq!(accounts.accounts().count(), 2);
+        assert_eq!(accounts.get_account_by_id("alice").unwrap().balance, 5);
+
+        assert_eq!(undo.revert(&mut accounts), 10);
+        assert_eq!(accounts, before);
+    }
+}
@@ -1,5 +1,8 @@
-use std::time::{SystemTime, UNIX_EPOCH};
-use crate::types::{AccountId, Block, Blockchain, COEFFICIENT_LENGTH, Error, Hash, Target};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::types::{AccountId, Block, BlockHeader, Blockchain, Hash, HeaderHasher, MiningError, Target};
 use blake2::{Blake2s, Digest};
 use ed25519_dalek::{Keypair};
 use rand::Rng;
@@ -24,67 +27,166 @@ pub fn generate_timestamp() -> u64 {
     since_the_epoch.as_secs()
 }
 
-pub fn hash_to_bits(hash: Hash) -> Hash {
-    let exponent;    // size of the hash in bytes
-    let coefficient: &str;                      // initial 3 bytes of the hash
-    let mut result = String::new();      // 8 digits (4 bytes) long
+#[derive(Debug, Clone, PartialEq)]
+pub enum MiningResult {
+    Found { nonce: u128, hash: Hash },
+    Cancelled,
+    Exhausted,
+}
+
+#[derive(Debug, Clone)]
+pub struct MiningReport {
+    pub result: MiningResult,
+    pub hashes: u64,
+    pub elapsed: Duration,
+}
 
-    let beginning = find_beginning_of_hash(hash.clone());
-    let mut new_hash;
-    new_hash = hash[beginning..].to_string();
+/// Shared flag which stops a running miner, e.g. when a new head arrives.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
 
-    if new_hash.len() % 2 != 0 {
-        new_hash = "0".to_string() + &new_hash;
+impl CancelToken {
+    pub fn new() -> Self {
+        Default::default()
     }
 
-    let number_of_bytes = new_hash.len()/2;
-    exponent = hex::encode(vec![number_of_bytes as u8]);
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
 
-    coefficient = &new_hash[..COEFFICIENT_LENGTH];  // 3 bytes = 6 digits in hex
-    result += exponent.as_str();
-    result += coefficient;
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
 
-    result
+/// Proof-of-work miner which splits the nonce space across worker threads.
+///
+/// Worker `i` of `n` tries nonces `start + i`, `start + i + n`, ... until one of the workers
+/// finds a solution, the miner is cancelled or the nonce range is exhausted.
+#[derive(Debug, Clone)]
+pub struct Miner {
+    threads: usize,
+    start_nonce: u128,
+    end_nonce: u128,
+    cancel: CancelToken,
 }
 
-pub fn find_beginning_of_hash(hash: Hash) -> usize {
-    let length_range = hash.len();
-    let length: Vec<usize> = (0..length_range).collect();
-    let length_iter = length.iter();
+impl Miner {
+    pub fn new(threads: usize) -> Self {
+        Self {
+            threads: threads.max(1),
+            start_nonce: 1,
+            end_nonce: u128::MAX,
+            cancel: CancelToken::new(),
+        }
+    }
+
+    /// Restricts the miner to nonces in `start..end`.
+    pub fn with_nonce_range(mut self, start: u128, end: u128) -> Self {
+        self.start_nonce = start;
+        self.end_nonce = end;
+        self
+    }
+
+    pub fn with_cancel_token(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
 
-    for iter in length_iter {
-        if hash.chars().nth(*iter).unwrap() != '0' {
-            return *iter
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    /// Searches for a nonce meeting `target` and sets it on `block` if found.
+    pub fn mine(&self, block: &mut Block, target: Target) -> MiningReport {
+        let mut header = block.header.clone();
+        let report = self.mine_header(&mut header, target);
+        if let MiningResult::Found { nonce, .. } = report.result {
+            block.set_nonce(nonce);
         }
+
+        report
     }
 
-    return 0
-}
+    /// Searches for a nonce meeting `target` and sets it on `header` if found.
+    /// Only the fixed-size header is hashed, so the hash rate doesn't depend on the block size.
+    pub fn mine_header(&self, header: &mut BlockHeader, target: Target) -> MiningReport {
+        let started = Instant::now();
+        let hasher = HeaderHasher::new(header);
+        let stop = AtomicBool::new(false);
+        let hashes = AtomicU64::new(0);
+        let solution: Mutex<Option<(u128, Hash)>> = Mutex::new(None);
+
+        thread::scope(|scope| {
+            for worker in 0..self.threads {
+                let (hasher, stop, hashes, solution) = (&hasher, &stop, &hashes, &solution);
+
+                scope.spawn(move || {
+                    let mut tried = 0;
+                    let mut nonce = self.start_nonce.checked_add(worker as u128);
+
+                    while let Some(current) = nonce.filter(|nonce| *nonce < self.end_nonce) {
+                        if stop.load(Ordering::Relaxed) || self.cancel.is_cancelled() {
+                            break;
+                        }
+
+                        let hash = hasher.hash(current);
+                        tried += 1;
+                        if target.is_met_by_bytes(&hash) {
+                            solution.lock().unwrap().get_or_insert((current, hex::encode(hash)));
+                            stop.store(true, Ordering::Relaxed);
+                            break;
+                        }
+
+                        nonce = current.checked_add(self.threads as u128);
+                    }
+
+                    hashes.fetch_add(tried, Ordering::Relaxed);
+                });
+            }
+        });
+
+        let result = match solution.into_inner().unwrap() {
+            Some((nonce, hash)) => {
+                header.nonce = nonce;
+                MiningResult::Found { nonce, hash }
+            }
+            None if self.cancel.is_cancelled() => MiningResult::Cancelled,
+            None => MiningResult::Exhausted,
+        };
 
-pub fn check_target(target: Target, hash: Hash) -> bool {
-    let result = u64::from_str_radix(&(hash_to_bits(hash.clone())), 16);
-    if result.unwrap() < target {
-        return true;
+        MiningReport {
+            result,
+            hashes: hashes.into_inner(),
+            elapsed: started.elapsed(),
+        }
     }
+}
 
-    false
+impl Default for Miner {
+    fn default() -> Self {
+        Miner::new(thread::available_parallelism().map_or(1, |threads| threads.get()))
+    }
 }
 
-pub fn mining(block: &mut Block, bc: &Blockchain) -> Result<(), Error> {
-    let mut nonce: u128 = 1;
-    block.set_nonce(nonce.clone());
+pub fn mining(block: &mut Block, bc: &Blockchain) -> Result<(), MiningError> {
+    // complete the header with what the chain expects on top of the parent
+    let target = bc.next_target(&block.header.prev_hash).unwrap_or(bc.current_target);
+    block.header.height = bc.next_height(&block.header.prev_hash).unwrap_or_default();
+    block.header.bits = target.to_compact();
+    block.header.base_fee = bc.next_base_fee(&block.header.prev_hash).unwrap_or_default();
 
-    while check_target(bc.current_target.clone(), block.hash.clone().unwrap()) == false {
-        nonce += 1;
-        block.set_nonce(nonce.clone());
+    match Miner::default().mine(block, target).result {
+        MiningResult::Found { .. } => Ok(()),
+        MiningResult::Cancelled => Err(MiningError::Cancelled),
+        MiningResult::Exhausted => Err(MiningError::Exhausted),
     }
-
-    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::traits::Hashable;
 
     #[test]
     fn test_generate_id() {
@@ -92,11 +194,69 @@ mod tests {
     }
 
     #[test]
-    fn test_hash_to_bits() {
-        let target = "00000000000000000000000000000000000333a1000000000000000000000000".to_string();
-        let result = hash_to_bits(target.clone());
-        let target = u64::from_str_radix(&result, 16);
-        assert_eq!(result.clone(), "0f0333a1".to_string());
-        assert_eq!(target.unwrap(), 251868065)
+    fn test_miner_found() {
+        let mut block = Block::new(None);
+        let report = Miner::new(4).mine(&mut block, Target::MAX);
+
+        match report.result {
+            MiningResult::Found { hash, .. } => {
+                assert!(block.verify());
+                assert_eq!(block.hash, Some(hash.clone()));
+                assert!(Target::MAX.is_met_by(&hash));
+            }
+            result => panic!("unexpected mining result: {:?}", result),
+        }
+        assert!(report.hashes > 0);
+    }
+
+    #[test]
+    fn test_mine_header() {
+        let mut header = Block::new(None).header;
+        let report = Miner::new(2).mine_header(&mut header, Target::MAX);
+
+        match report.result {
+            MiningResult::Found { nonce, hash } => {
+                assert_eq!(header.nonce, nonce);
+                assert_eq!(header.hash(), hash);
+                assert!(Target::MAX.is_met_by(&hash));
+            }
+            result => panic!("unexpected mining result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_miner_cancelled() {
+        let mut block = Block::new(None);
+        let miner = Miner::new(2);
+        miner.cancel_token().cancel();
+
+        let report = miner.mine(&mut block, Target::default());
+        assert_eq!(report.result, MiningResult::Cancelled);
+        assert_eq!(report.hashes, 0);
+    }
+
+    #[test]
+    fn test_miner_cancelled_while_running() {
+        let mut block = Block::new(None);
+        let miner = Miner::new(2);
+        let cancel = miner.cancel_token();
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            cancel.cancel();
+        });
+
+        let report = miner.mine(&mut block, Target::default());
+        canceller.join().unwrap();
+        assert_eq!(report.result, MiningResult::Cancelled);
+        assert!(report.hashes > 0);
+    }
+
+    #[test]
+    fn test_miner_exhausted() {
+        let mut block = Block::new(None);
+        let report = Miner::new(3).with_nonce_range(10, 110).mine(&mut block, Target::default());
+
+        assert_eq!(report.result, MiningResult::Exhausted);
+        assert_eq!(report.hashes, 100);
     }
 }
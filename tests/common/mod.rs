@@ -1,10 +1,18 @@
-use ed25519_dalek::Signer;
-use blockchain_workshop::traits::Hashable;
-use blockchain_workshop::types::{AccountId, Balance, Block, Blockchain, Error, Transaction, TransactionData};
+use blockchain_workshop::types::{AccountId, Balance, Block, BlockError, Blockchain, Hash, Timestamp, Transaction,
+                                 TransactionData};
 use blockchain_workshop::utils::{generate_keypair, mining};
+use ed25519_dalek::Keypair;
 
 pub fn create_block(bc: &mut Blockchain, user1_id: AccountId) -> Block {
-    let mut block = Block::new(bc.get_last_block_hash());
+    let prev_hash = bc.get_last_block_hash();
+    create_block_on(bc, prev_hash, user1_id)
+}
+
+pub fn create_block_on(bc: &mut Blockchain, prev_hash: Option<Hash>, user1_id: AccountId) -> Block {
+    let mut block = Block::new(prev_hash.clone());
+    if let Some(prev_hash) = &prev_hash {
+        block.add_transaction(coinbase_on(bc, prev_hash));
+    }
 
     let user1_keypair = generate_keypair();
     let user1_pk = user1_keypair.public;
@@ -12,8 +20,7 @@ pub fn create_block(bc: &mut Blockchain, user1_id: AccountId) -> Block {
         Transaction::new(TransactionData::CreateAccount(user1_id.clone(), user1_pk),
                          Some(user1_id.clone()));
 
-    tx_create_account_user1.signature =
-        Some(user1_keypair.sign(tx_create_account_user1.hash().as_bytes()).to_bytes());
+    tx_create_account_user1.sign(&user1_keypair, bc.chain_id());
 
     block.add_transaction(tx_create_account_user1.clone());
 
@@ -22,10 +29,23 @@ pub fn create_block(bc: &mut Blockchain, user1_id: AccountId) -> Block {
     block.clone()
 }
 
+pub fn create_block_at(bc: &mut Blockchain, timestamp: Timestamp, user1_id: AccountId) -> Block {
+    let mut block = create_block(bc, user1_id);
+    block.set_timestamp(timestamp);
+
+    assert!(mining(&mut block, bc).is_ok());
+
+    block
+}
+
 pub fn create_block_and_tx(bc: &mut Blockchain, mint_amount: Vec<Balance>, tx_amount: Balance,
     user1_id: AccountId, user2_id: AccountId) -> Block {
 
-    let mut block = Block::new(bc.get_last_block_hash());
+    let prev_hash = bc.get_last_block_hash();
+    let mut block = Block::new(prev_hash.clone());
+    if let Some(prev_hash) = &prev_hash {
+        block.add_transaction(coinbase_on(bc, prev_hash));
+    }
     let user1_keypair = generate_keypair();
     let user1_pk = user1_keypair.public;
 
@@ -41,8 +61,7 @@ pub fn create_block_and_tx(bc: &mut Blockchain, mint_amount: Vec<Balance>, tx_am
         None,
     );
 
-    tx_create_account_user1.signature =
-        Some(user1_keypair.sign(tx_create_account_user1.hash().as_bytes()).to_bytes());
+    tx_create_account_user1.sign(&user1_keypair, bc.chain_id());
 
     let user2_keypair = generate_keypair();
     let user2_pk = user2_keypair.public;
@@ -59,8 +78,7 @@ pub fn create_block_and_tx(bc: &mut Blockchain, mint_amount: Vec<Balance>, tx_am
         None,
     );
 
-    tx_create_account_user2.signature =
-        Some(user2_keypair.sign(tx_create_account_user2.hash().as_bytes()).to_bytes());
+    tx_create_account_user2.sign(&user2_keypair, bc.chain_id());
 
     let mut tx_transfer1 = Transaction::new(
         TransactionData::Transfer {
@@ -70,8 +88,7 @@ pub fn create_block_and_tx(bc: &mut Blockchain, mint_amount: Vec<Balance>, tx_am
         Some(user1_id.clone()),
     );
 
-    tx_transfer1.signature =
-        Some(user1_keypair.sign(tx_transfer1.hash().as_bytes()).to_bytes());
+    tx_transfer1.sign(&user1_keypair, bc.chain_id());
 
     block.add_transaction(tx_create_account_user1.clone());
     block.add_transaction(tx_mint_init_supply_user1.clone());
@@ -84,11 +101,17 @@ pub fn create_block_and_tx(bc: &mut Blockchain, mint_amount: Vec<Balance>, tx_am
     block.clone()
 }
 
+// Transactions which don't start with a coinbase get one claiming nothing in front
 pub fn append_block_with_tx(
     bc: &mut Blockchain,
     transactions: Vec<Transaction>,
-) -> Result<(), Error> {
-    let mut block = Block::new(bc.get_last_block_hash());
+) -> Result<(), BlockError> {
+    let prev_hash = bc.get_last_block_hash();
+    let mut block = Block::new(prev_hash.clone());
+    match (&prev_hash, transactions.first()) {
+        (Some(prev_hash), Some(tx)) if !tx.is_coinbase() => block.add_transaction(coinbase_on(bc, prev_hash)),
+        _ => {}
+    }
 
     for tx in transactions {
         block.add_transaction(tx);
@@ -97,4 +120,39 @@ pub fn append_block_with_tx(
     assert!(mining(&mut block, bc).is_ok());
 
     bc.append_block(block)
-}
\ No newline at end of file
+}
+
+pub fn append_genesis(bc: &mut Blockchain, accounts: &[(AccountId, &Keypair, Balance)]) -> Result<(), BlockError> {
+    let mut transactions = Vec::new();
+    for (account_id, keypair, amount) in accounts {
+        let mut tx_create_account = Transaction::new(
+            TransactionData::CreateAccount(account_id.clone(), keypair.public), Some(account_id.clone()));
+        tx_create_account.sign(keypair, bc.chain_id());
+        transactions.push(tx_create_account);
+        transactions.push(Transaction::new(
+            TransactionData::MintInitialSupply { to: account_id.clone(), amount: *amount }, None));
+    }
+
+    append_block_with_tx(bc, transactions)
+}
+
+pub fn transfer_tx(bc: &Blockchain, from: &AccountId, keypair: &Keypair, to: &AccountId, amount: Balance,
+                   fee: Balance, nonce: u128) -> Transaction {
+    let mut tx = Transaction::new(TransactionData::Transfer { to: to.clone(), amount }, Some(from.clone()));
+    tx.nonce = nonce;
+    tx.fee = fee;
+    tx.sign(keypair, bc.chain_id());
+
+    tx
+}
+
+// Coinbase claiming nothing for the first account of the genesis block, which every branch has
+pub fn coinbase_on(bc: &Blockchain, prev_hash: &Hash) -> Transaction {
+    let height = bc.get_block_by_hash(prev_hash).map_or(0, |parent| parent.header.height + 1);
+    let miner = match bc.blocks.genesis().and_then(|genesis| genesis.transactions.first()) {
+        Some(Transaction { data: TransactionData::CreateAccount(account_id, _), .. }) => account_id.clone(),
+        _ => AccountId::new(),
+    };
+
+    Transaction::new(TransactionData::Coinbase { to: miner, amount: 0, height }, None)
+}
@@ -1,9 +1,12 @@
 use ed25519_dalek::Signer;
-use blockchain_workshop::traits::{Hashable, WorldState};
-use blockchain_workshop::types::{Block, Blockchain, Transaction, TransactionData};
-use blockchain_workshop::utils::{generate_account_id, generate_keypair, mining};
+use blockchain_workshop::traits::{Decode, Encode, Hashable, WorldState};
+use blockchain_workshop::types::{Account, Balance, Block, BlockError, BlockLimits, Blockchain, BlockStatus, EXPECTED_TIME, ExecutionContext, FeeMarket, FixedInterval, HEADER_SIZE, INITIAL_SUBSIDY, Lwma, MAX_ORPHAN_BLOCKS, MemoryState, MAX_TARGET_BITS,
+                                 MempoolError, ORPHAN_EXPIRY, RewardSchedule, StateError, StateOverlay, Target, TxError,
+                                 Transaction, TransactionData};
+use blockchain_workshop::utils::{generate_account_id, generate_keypair, mining, Miner, MiningResult};
 mod common;
-use common::{append_block_with_tx, create_block, create_block_and_tx};
+use common::{append_block_with_tx, append_genesis, create_block, create_block_and_tx, create_block_at, create_block_on,
+             coinbase_on, transfer_tx};
 
 #[test]
 fn test_create_blockchain() {
@@ -39,9 +42,8 @@ fn test_state_rollback_works() {
     // true block
     let mut tx_create_satoshi =
         Transaction::new(TransactionData::CreateAccount(
-            satoshi_id.clone(), satoshi_keypair.public.clone()), Some(satoshi_id.clone()));
-    tx_create_satoshi.signature =
-        Some(satoshi_keypair.sign(tx_create_satoshi.hash().as_bytes()).to_bytes());
+            satoshi_id.clone(), satoshi_keypair.public), Some(satoshi_id.clone()));
+    tx_create_satoshi.sign(&satoshi_keypair, bc.chain_id());
 
     let mut block = Block::new(None);
     block.add_transaction(tx_create_satoshi);
@@ -53,16 +55,14 @@ fn test_state_rollback_works() {
     let mut block = Block::new(bc.get_last_block_hash());
     let mut tx_create_alice =
         Transaction::new(TransactionData::CreateAccount(
-            alice_id.clone(), alice_keypair.public.clone()), Some(alice_id.clone()));
+            alice_id.clone(), alice_keypair.public), Some(alice_id.clone()));
 
     let mut tx_create_bob =
         Transaction::new(TransactionData::CreateAccount(
-            bob_id.clone(), bob_keypair.public.clone()), Some(bob_id.clone()));
+            bob_id.clone(), bob_keypair.public), Some(bob_id.clone()));
 
-    tx_create_alice.signature =
-        Some(alice_keypair.sign(tx_create_alice.hash().as_bytes()).to_bytes());
-    tx_create_bob.signature =
-        Some(bob_keypair.sign(tx_create_bob.hash().as_bytes()).to_bytes());
+    tx_create_alice.sign(&alice_keypair, bc.chain_id());
+    tx_create_bob.sign(&bob_keypair, bc.chain_id());
 
     block.add_transaction(tx_create_alice);
     block.add_transaction(tx_create_bob.clone());
@@ -70,9 +70,9 @@ fn test_state_rollback_works() {
     assert!(mining(&mut block, &bc).is_ok());
 
     assert!(bc.append_block(block).is_err());
-    assert!(bc.get_account_by_id(satoshi_id.clone()).is_some());
-    assert!(bc.get_account_by_id(alice_id.clone()).is_none());
-    assert!(bc.get_account_by_id(bob_id.clone()).is_none());
+    assert!(bc.get_account_by_id(&satoshi_id).is_some());
+    assert!(bc.get_account_by_id(&alice_id).is_none());
+    assert!(bc.get_account_by_id(&bob_id).is_none());
 }
 
 #[test]
@@ -94,8 +94,7 @@ fn test_validate_blockchain() {
         None,
     );
 
-    tx_create_account.signature =
-        Some(user1_keypair.sign(tx_create_account.hash().as_bytes()).to_bytes());
+    tx_create_account.sign(&user1_keypair, bc.chain_id());
 
     assert!(
         append_block_with_tx(bc, vec![tx_create_account, tx_mint_init_supply]).is_ok()
@@ -125,10 +124,10 @@ fn test_hash() {
     let mut block = Block::new(None);
     let user1_keypair = generate_keypair();
     let mut tx = Transaction::new(
-        TransactionData::CreateAccount("alice".to_string(), user1_keypair.public.clone()),
+        TransactionData::CreateAccount("alice".to_string(), user1_keypair.public),
         Some("alice".to_string())
     );
-    tx.signature = Some(user1_keypair.sign(tx.hash().as_bytes()).to_bytes());
+    tx.sign(&user1_keypair, 0);
 
     let hash1 = block.hash();
 
@@ -159,12 +158,11 @@ fn test_create_genesis_block() {
     None,
     );
 
-    tx_create_account.signature =
-        Some(user1_keypair.sign(tx_create_account.hash().as_bytes()).to_bytes());
+    tx_create_account.sign(&user1_keypair, bc.chain_id());
 
     assert!(append_block_with_tx(bc, vec![tx_create_account, tx_mint_init_supply]).is_ok());
 
-    let satoshi = bc.get_account_by_id(user1_id.clone());
+    let satoshi = bc.get_account_by_id(&user1_id);
 
     assert!(satoshi.is_some());
     assert_eq!(satoshi.unwrap().balance, 100_000_000);
@@ -190,8 +188,7 @@ fn test_create_genesis_block_fails() {
     None,
     );
 
-    tx_create_account.signature =
-        Some(user1_keypair.sign(tx_create_account.hash().as_bytes()).to_bytes());
+    tx_create_account.sign(&user1_keypair, bc.chain_id());
 
     let mut block = Block::new(None);
     block.add_transaction(tx_mint_init_supply);
@@ -199,8 +196,8 @@ fn test_create_genesis_block_fails() {
     assert!(mining(&mut block, &bc).is_ok());
 
     assert_eq!(
-        bc.append_block(block).err().unwrap(),
-        "Error during tx execution: Invalid account.".to_string()
+        bc.append_block(block),
+        Err(BlockError::Transaction { index: 0, error: TxError::UnknownAccount(user1_id) })
     );
 }
 
@@ -216,15 +213,14 @@ fn test_account_creating() {
         Transaction::new(TransactionData::CreateAccount(user1_id.clone(), user1_pk),
                          Some(user1_id.clone()));
 
-    tx_create_account_user1.signature =
-        Some(user1_keypair.sign(tx_create_account_user1.hash().as_bytes()).to_bytes());
+    tx_create_account_user1.sign(&user1_keypair, bc.chain_id());
 
     block.add_transaction(tx_create_account_user1.clone());
     assert!(mining(&mut block, bc).is_ok());
 
     assert!(bc.append_block(block.clone()).is_ok());
 
-    let test_user = bc.get_account_by_id(user1_id.clone());
+    let test_user = bc.get_account_by_id(&user1_id);
     assert!(test_user.is_some());
     assert_eq!(test_user.unwrap().public_key, user1_pk);
 }
@@ -240,8 +236,8 @@ fn test_accounts_exist() {
 
     assert!(bc.append_block(block.clone()).is_ok());
 
-    let test_user1 = bc.get_account_by_id(user1_id.clone());
-    let test_user2 = bc.get_account_by_id(user2_id.clone());
+    let test_user1 = bc.get_account_by_id(&user1_id);
+    let test_user2 = bc.get_account_by_id(&user2_id);
     assert!(test_user1.is_some());
     assert!(test_user2.is_some());
     assert_eq!(test_user1.unwrap().balance, 910);
@@ -285,8 +281,7 @@ fn test_receiver_doesnt_exist() {
     None,
     );
 
-    tx_create_account.signature =
-        Some(user1_keypair.sign(tx_create_account.hash().as_bytes()).to_bytes());
+    tx_create_account.sign(&user1_keypair, bc.chain_id());
 
     assert!(
         append_block_with_tx(bc, vec![tx_create_account.clone(), tx_mint_init_supply.clone()],).is_ok()
@@ -338,8 +333,7 @@ fn test_invalid_signature() {
         append_block_with_tx(bc, vec![tx_create_account_user1.clone()]).is_err()
     );
 
-    tx_create_account_user1.signature =
-        Some(user1_keypair.sign(tx_create_account_user1.hash().as_bytes()).to_bytes());
+    tx_create_account_user1.sign(&user1_keypair, bc.chain_id());
     assert!(
         append_block_with_tx(bc, vec![tx_create_account_user1.clone()]).is_ok()
     );
@@ -359,4 +353,999 @@ fn creating_account_false() {
     assert!(
         append_block_with_tx(bc, vec![tx_create_account_user1.clone()]).is_err()
     );
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_fork_choice_reorganization() {
+    let bc = &mut Blockchain::new();
+    let genesis = create_block(bc, "satoshi".to_string());
+    assert!(bc.append_block(genesis.clone()).is_ok());
+
+    let block_a1 = create_block(bc, "alice".to_string());
+    assert!(bc.append_block(block_a1.clone()).is_ok());
+
+    // same work as the main chain: kept as a side branch
+    let block_b1 = create_block_on(bc, genesis.hash.clone(), "bob".to_string());
+    assert!(bc.append_block(block_b1.clone()).is_ok());
+    assert_eq!(bc.get_last_block_hash(), block_a1.hash.clone());
+    assert_eq!(bc.side_blocks_len(), 1);
+    assert!(bc.get_account_by_id("bob").is_none());
+
+    // heavier branch: reorganization
+    let work_before = bc.total_work();
+    let block_b2 = create_block_on(bc, block_b1.hash.clone(), "carol".to_string());
+    assert!(bc.append_block(block_b2.clone()).is_ok());
+
+    assert_eq!(bc.get_last_block_hash(), block_b2.hash.clone());
+    assert!(bc.total_work() > work_before);
+    assert_eq!(bc.len(), 3);
+    assert_eq!(bc.side_blocks_len(), 1);
+    assert!(bc.get_account_by_id("satoshi").is_some());
+    assert!(bc.get_account_by_id("alice").is_none());
+    assert!(bc.get_account_by_id("bob").is_some());
+    assert!(bc.get_account_by_id("carol").is_some());
+    assert!(bc.validate().is_ok());
+
+    // the reorganized main chain is indexed by height and hash, the old branch is still known
+    assert_eq!(bc.get_block_by_height(1), Some(&block_b1));
+    assert_eq!(bc.get_block_by_height(2), Some(&block_b2));
+    assert_eq!(bc.get_block_by_height(3), None);
+    assert_eq!(bc.get_block_by_hash(&genesis.hash.clone().unwrap()), Some(&genesis));
+    assert_eq!(bc.get_block_by_hash(&block_a1.hash.clone().unwrap()), Some(&block_a1));
+    assert_eq!(bc.get_block_by_hash(&"unknown".to_string()), None);
+}
+
+#[test]
+fn test_fork_choice_invalid_branch() {
+    let bc = &mut Blockchain::new();
+    let genesis = create_block(bc, "satoshi".to_string());
+    assert!(bc.append_block(genesis.clone()).is_ok());
+
+    let block_a1 = create_block(bc, "alice".to_string());
+    assert!(bc.append_block(block_a1.clone()).is_ok());
+
+    let block_b1 = create_block_on(bc, genesis.hash.clone(), "bob".to_string());
+    assert!(bc.append_block(block_b1.clone()).is_ok());
+
+    // the heavier branch can't be executed, the main chain is kept
+    let mut block_b2 = Block::new(block_b1.hash.clone());
+    block_b2.add_transaction(Transaction::new(
+        TransactionData::Transfer { to: "bob".to_string(), amount: 100 },
+        Some("nobody".to_string()),
+    ));
+    assert!(mining(&mut block_b2, bc).is_ok());
+    assert!(bc.append_block(block_b2).is_err());
+
+    assert_eq!(bc.get_last_block_hash(), block_a1.hash.clone());
+    assert!(bc.get_account_by_id("alice").is_some());
+    assert!(bc.get_account_by_id("bob").is_none());
+    assert!(bc.validate().is_ok());
+
+    // an invalid side block is only executed once a descendant outweighs the main chain, its
+    // other descendants are forgotten along with it
+    for name in &["alice2", "alice3"] {
+        let block = create_block(bc, name.to_string());
+        assert!(bc.append_block(block).is_ok());
+    }
+    let mut block_b2 = Block::new(block_b1.hash.clone());
+    block_b2.add_transaction(coinbase_on(bc, block_b1.hash.as_ref().unwrap()));
+    block_b2.add_transaction(Transaction::new(
+        TransactionData::Transfer { to: "bob".to_string(), amount: 100 },
+        Some("nobody".to_string()),
+    ));
+    assert!(mining(&mut block_b2, bc).is_ok());
+    assert!(bc.append_block(block_b2.clone()).is_ok());
+    let block_b3 = create_block_on(bc, block_b2.hash.clone(), "carol".to_string());
+    assert!(bc.append_block(block_b3.clone()).is_ok());
+    let block_c3 = create_block_on(bc, block_b2.hash.clone(), "dave".to_string());
+    assert!(bc.append_block(block_c3.clone()).is_ok());
+    assert_eq!(bc.side_blocks_len(), 4);
+
+    let block_b4 = create_block_on(bc, block_b3.hash.clone(), "erin".to_string());
+    assert!(matches!(bc.append_block(block_b4), Err(BlockError::InvalidBranch { .. })));
+    assert_eq!(bc.len(), 4);
+    assert_eq!(bc.side_blocks_len(), 1);
+    assert_eq!(bc.get_block_by_hash(&block_c3.hash.clone().unwrap()), None);
+    let block_c4 = create_block_on(bc, block_c3.hash.clone(), "frank".to_string());
+    assert_eq!(bc.append_block(block_c4), Err(BlockError::UnknownParent(block_c3.hash.unwrap())));
+    assert!(bc.validate().is_ok());
+}
+
+#[test]
+fn test_unknown_parent() {
+    let bc = &mut Blockchain::new();
+    let genesis = create_block(bc, "satoshi".to_string());
+    assert!(bc.append_block(genesis).is_ok());
+
+    let block = create_block_on(bc, Some("unknown".to_string()), "alice".to_string());
+    assert_eq!(bc.append_block(block), Err(BlockError::UnknownParent("unknown".to_string())));
+    assert_eq!(bc.orphans_len(), 0);
+}
+
+#[test]
+fn test_second_genesis_rejected() {
+    let bc = &mut Blockchain::new();
+    let genesis = create_block(bc, "satoshi".to_string());
+    assert!(bc.append_block(genesis).is_ok());
+
+    let block = create_block_on(bc, None, "alice".to_string());
+    assert_eq!(bc.append_block(block), Err(BlockError::MissingPrevHash));
+    assert_eq!(bc.len(), 1);
+    assert_eq!(bc.side_blocks_len(), 0);
+}
+
+#[test]
+fn test_orphan_blocks() {
+    // blocks as produced by another node
+    let source = &mut Blockchain::new();
+    let mut blocks = Vec::new();
+    for _ in 0..4 {
+        let block = create_block(source, generate_account_id());
+        assert!(source.append_block(block.clone()).is_ok());
+        blocks.push(block);
+    }
+
+    let bc = &mut Blockchain::new();
+    assert_eq!(bc.receive_block(blocks[0].clone()), Ok(BlockStatus::Appended));
+    assert_eq!(bc.receive_block(blocks[3].clone()), Ok(BlockStatus::Orphan));
+    assert_eq!(bc.receive_block(blocks[2].clone()), Ok(BlockStatus::Orphan));
+    assert!(bc.receive_block(blocks[2].clone()).is_err());
+    assert_eq!(bc.orphans_len(), 2);
+    assert_eq!(bc.len(), 1);
+
+    // the missing parent connects the held descendants
+    assert_eq!(bc.receive_block(blocks[1].clone()), Ok(BlockStatus::Appended));
+    assert_eq!(bc.orphans_len(), 0);
+    assert_eq!(bc.len(), 4);
+    assert_eq!(bc.get_last_block_hash(), source.get_last_block_hash());
+    assert!(bc.validate_full().is_ok());
+}
+
+#[test]
+fn test_orphan_pool_limits() {
+    let bc = &mut Blockchain::new();
+    let genesis = create_block(bc, "satoshi".to_string());
+    assert!(bc.append_block(genesis).is_ok());
+    let orphan = |bc: &mut Blockchain, parent: usize| {
+        create_block_on(bc, Some(format!("{:064x}", parent)), generate_account_id())
+    };
+
+    // an orphan can't declare an easier target than the head's
+    let mut easy = orphan(bc, 0);
+    let easy_target = Target::from_compact(0x2100_ffff).unwrap();
+    easy.header.bits = easy_target.to_compact();
+    assert!(matches!(Miner::new(1).mine(&mut easy, easy_target).result, MiningResult::Found { .. }));
+    assert_eq!(bc.receive_block_at(easy, 1_000), Err(BlockError::InsufficientWork));
+
+    // a full pool drops the oldest orphan
+    let first = orphan(bc, 1);
+    assert_eq!(bc.receive_block_at(first.clone(), 1_000), Ok(BlockStatus::Orphan));
+    for parent in 2..=MAX_ORPHAN_BLOCKS {
+        let block = orphan(bc, parent);
+        assert_eq!(bc.receive_block_at(block, 1_001), Ok(BlockStatus::Orphan));
+    }
+    assert_eq!(bc.receive_block_at(first.clone(), 1_001), Err(BlockError::AlreadyExists));
+    let block = orphan(bc, 0);
+    assert_eq!(bc.receive_block_at(block, 1_002), Ok(BlockStatus::Orphan));
+    assert_eq!(bc.orphans_len(), MAX_ORPHAN_BLOCKS);
+    assert_eq!(bc.receive_block_at(first, 1_002), Ok(BlockStatus::Orphan));
+    assert_eq!(bc.orphans_len(), MAX_ORPHAN_BLOCKS);
+
+    // and forgets the ones held for too long
+    let block = orphan(bc, 0);
+    assert_eq!(bc.receive_block_at(block, 1_001 + ORPHAN_EXPIRY), Ok(BlockStatus::Orphan));
+    assert_eq!(bc.orphans_len(), 3);
+}
+
+#[test]
+fn test_fixed_interval_difficulty() {
+    let bc = &mut Blockchain::with_difficulty(FixedInterval::new(1000, EXPECTED_TIME));
+
+    for _ in 0..5 {
+        let block = create_block(bc, generate_account_id());
+        assert!(bc.append_block(block).is_ok());
+        assert_eq!(bc.get_current_target(), Target::MAX);
+    }
+    assert!(bc.validate().is_ok());
+}
+
+#[test]
+fn test_lwma_difficulty() {
+    let bc = &mut Blockchain::with_difficulty(Lwma::new(10, EXPECTED_TIME));
+
+    let genesis = create_block(bc, generate_account_id());
+    assert!(bc.append_block(genesis).is_ok());
+    assert_eq!(bc.get_current_target(), Target::MAX);
+
+    // blocks come faster than expected, the difficulty goes up
+    let block = create_block(bc, generate_account_id());
+    assert!(bc.append_block(block).is_ok());
+    assert!(bc.get_current_target() < Target::MAX);
+}
+
+#[test]
+fn test_block_timestamp_rules() {
+    let bc = &mut Blockchain::with_difficulty(FixedInterval::new(1000, EXPECTED_TIME));
+    let now = 1_000;
+
+    for timestamp in 100..105 {
+        let block = create_block_at(bc, timestamp, generate_account_id());
+        assert!(bc.append_block_at(block, now).is_ok());
+    }
+
+    // median of 100..105 is 102
+    let block = create_block_at(bc, 101, generate_account_id());
+    assert_eq!(bc.append_block_at(block, now), Err(BlockError::TimestampBeforeMedian { timestamp: 101, median: 102 }));
+
+    let block = create_block_at(bc, now + 61, generate_account_id());
+    assert_eq!(bc.append_block_at(block, now), Err(BlockError::TimestampInFuture(now + 61)));
+
+    let block = create_block_at(bc, 102, generate_account_id());
+    assert!(bc.append_block_at(block, now).is_ok());
+
+    let block = create_block_at(bc, now + 60, generate_account_id());
+    assert!(bc.append_block_at(block, now).is_ok());
+    assert_eq!(bc.len(), 7);
+}
+
+#[test]
+fn test_block_header_commitments() {
+    let bc = &mut Blockchain::new();
+
+    // the timestamp is part of the proof-of-work
+    let mut block = create_block(bc, generate_account_id());
+    block.header.timestamp += 1;
+    assert!(!block.verify());
+    assert!(bc.append_block(block).is_err());
+
+    // so are the transactions, through the Merkle root
+    let mut block = create_block(bc, generate_account_id());
+    block.transactions.push(block.transactions[0].clone());
+    assert!(!block.verify());
+
+    // a hash can't be relabelled with another string for the same bytes
+    let genesis = create_block(bc, generate_account_id());
+    let mut block = create_block_on(bc, genesis.hash.clone(), generate_account_id());
+    block.header.prev_hash = block.header.prev_hash.map(|hash| hash.to_uppercase());
+    assert!(!block.verify());
+
+    let mut block = create_block(bc, generate_account_id());
+    block.header.bits = 0x1d00ffff;
+    block.set_nonce(0);
+    assert_eq!(bc.append_block(block), Err(BlockError::InvalidBits(0x1d00ffff)));
+
+    let mut block = create_block(bc, generate_account_id());
+    block.header.height = 5;
+    block.set_nonce(0);
+    assert_eq!(bc.append_block(block), Err(BlockError::InvalidHeight { height: 5, expected: 0 }));
+
+    let block = create_block(bc, generate_account_id());
+    assert_eq!(block.header.height, 0);
+    assert!(bc.append_block(block).is_ok());
+    let block = create_block(bc, generate_account_id());
+    assert_eq!(block.header.height, 1);
+    assert!(bc.append_block(block).is_ok());
+}
+
+#[test]
+fn test_transaction_inclusion_proof() {
+    let bc = &mut Blockchain::new();
+    let block = create_block_and_tx(bc, vec![10, 0], 5, "bob".to_string(), "alice".to_string());
+    assert!(bc.append_block(block.clone()).is_ok());
+
+    // a wallet only needs the header and the proof
+    let header = bc.blocks.head().unwrap().header.clone();
+    for tx in &block.transactions {
+        let proof = block.merkle_proof(&tx.hash()).unwrap();
+        assert!(proof.verify(&tx.full_hash(), &header.merkle_root));
+    }
+
+    let other = create_block(bc, generate_account_id());
+    let foreign_tx = &other.transactions[0];
+    assert!(block.merkle_proof(&foreign_tx.hash()).is_none());
+    let proof = block.merkle_proof(&block.transactions[0].hash()).unwrap();
+    assert!(!proof.verify(&foreign_tx.full_hash(), &header.merkle_root));
+
+    // the block commits to the signatures too
+    let mut stripped = block.clone();
+    stripped.transactions[0].signature = None;
+    assert!(!stripped.verify());
+}
+
+#[test]
+fn test_validate_full_replays_transactions() {
+    let bc = &mut Blockchain::new();
+    let block = create_block_and_tx(bc, vec![10, 0], 5, "bob".to_string(), "alice".to_string());
+    assert!(bc.append_block(block).is_ok());
+    for _ in 0..2 {
+        let block = create_block(bc, generate_account_id());
+        assert!(bc.append_block(block).is_ok());
+    }
+    assert!(bc.validate_full().is_ok());
+
+    // the re-mined head is still linked correctly, but its transaction can't be executed
+    let head = bc.blocks.iter_mut().next().unwrap();
+    let target = Target::from_compact(head.header.bits).unwrap();
+    head.transactions.truncate(1);
+    head.add_transaction(Transaction::new(
+        TransactionData::Transfer { to: "alice".to_string(), amount: 100 },
+        Some("bob".to_string()),
+    ));
+    assert!(matches!(Miner::new(1).mine(head, target).result, MiningResult::Found { .. }));
+
+    assert!(bc.validate().is_ok());
+    let invalid = bc.validate_full().unwrap_err();
+    assert_eq!(invalid.height, 2);
+    assert!(matches!(invalid.reason, BlockError::Transaction { index: 1, .. }));
+}
+
+#[test]
+fn test_validate_full_checks_targets() {
+    let bc = &mut Blockchain::new();
+    for _ in 0..3 {
+        let block = create_block_at(bc, 100, generate_account_id());
+        assert!(bc.append_block(block).is_ok());
+    }
+    assert!(bc.validate_full().is_ok());
+
+    // blocks came at once, the head can't claim the easiest target
+    let head = bc.blocks.iter_mut().next().unwrap();
+    head.header.bits = MAX_TARGET_BITS;
+    assert!(matches!(Miner::new(1).mine(head, Target::MAX).result, MiningResult::Found { .. }));
+
+    assert!(bc.validate().is_ok());
+    let invalid = bc.validate_full().unwrap_err();
+    assert_eq!(invalid.height, 2);
+    assert_eq!(invalid.reason, BlockError::InvalidBits(MAX_TARGET_BITS));
+}
+
+#[test]
+fn test_transfer_replay_protection() {
+    let bc = &mut Blockchain::new();
+    let satoshi_keypair = generate_keypair();
+    let satoshi_id = "satoshi".to_string();
+    let alice_keypair = generate_keypair();
+    let alice_id = "alice".to_string();
+
+    let mut tx_create_satoshi = Transaction::new(
+        TransactionData::CreateAccount(satoshi_id.clone(), satoshi_keypair.public), Some(satoshi_id.clone()));
+    tx_create_satoshi.sign(&satoshi_keypair, bc.chain_id());
+    let tx_mint = Transaction::new(
+        TransactionData::MintInitialSupply { to: satoshi_id.clone(), amount: 1000 }, None);
+    let mut tx_create_alice = Transaction::new(
+        TransactionData::CreateAccount(alice_id.clone(), alice_keypair.public), Some(alice_id.clone()));
+    tx_create_alice.sign(&alice_keypair, bc.chain_id());
+    assert!(append_block_with_tx(bc, vec![tx_create_satoshi, tx_mint, tx_create_alice]).is_ok());
+    assert_eq!(bc.get_next_nonce(&satoshi_id), Some(0));
+
+    let mut tx_transfer = Transaction::new(
+        TransactionData::Transfer { to: alice_id.clone(), amount: 100 }, Some(satoshi_id.clone()));
+    tx_transfer.sign(&satoshi_keypair, bc.chain_id());
+    assert!(append_block_with_tx(bc, vec![tx_transfer.clone()]).is_ok());
+    assert_eq!(bc.get_next_nonce(&satoshi_id), Some(1));
+
+    // the same signed transfer can't be included again
+    assert_eq!(
+        append_block_with_tx(bc, vec![tx_transfer.clone()]),
+        Err(BlockError::Transaction { index: 1, error: TxError::InvalidNonce { nonce: 0, expected: 1 } })
+    );
+    assert_eq!(bc.get_account_by_id(&alice_id).unwrap().balance, 100);
+
+    // only satoshi can spend satoshi's nonce, even on an account creation
+    let bob_keypair = generate_keypair();
+    let mut tx_create_bob = Transaction::new(
+        TransactionData::CreateAccount("bob".to_string(), bob_keypair.public), Some(satoshi_id.clone()));
+    tx_create_bob.nonce = 1;
+    tx_create_bob.sign(&bob_keypair, bc.chain_id());
+    assert_eq!(
+        append_block_with_tx(bc, vec![tx_create_bob.clone()]),
+        Err(BlockError::Transaction { index: 1, error: TxError::InvalidSignature })
+    );
+    assert_eq!(bc.submit_transaction(tx_create_bob), Err(MempoolError::Invalid(TxError::InvalidSignature)));
+    assert_eq!(bc.get_next_nonce(&satoshi_id), Some(1));
+
+    tx_transfer.nonce = 1;
+    tx_transfer.sign(&satoshi_keypair, bc.chain_id());
+    assert!(append_block_with_tx(bc, vec![tx_transfer]).is_ok());
+    assert_eq!(bc.get_next_nonce(&satoshi_id), Some(2));
+    assert_eq!(bc.get_account_by_id(&alice_id).unwrap().balance, 200);
+    assert_eq!(bc.get_next_nonce("nobody"), None);
+}
+
+#[test]
+fn test_signatures_are_bound_to_chain() {
+    let bc = &mut Blockchain::new().with_chain_id(1);
+    let other = &mut Blockchain::new().with_chain_id(2);
+    let satoshi_keypair = generate_keypair();
+    let satoshi_id = "satoshi".to_string();
+
+    let mut tx_create_satoshi = Transaction::new(
+        TransactionData::CreateAccount(satoshi_id.clone(), satoshi_keypair.public), Some(satoshi_id.clone()));
+    tx_create_satoshi.sign(&satoshi_keypair, bc.chain_id());
+    let tx_mint = Transaction::new(
+        TransactionData::MintInitialSupply { to: satoshi_id.clone(), amount: 1000 }, None);
+    let genesis = vec![tx_create_satoshi, tx_mint];
+
+    // the same genesis transactions don't verify on a chain with another ID
+    assert_eq!(append_block_with_tx(other, genesis.clone()), Err(BlockError::Transaction { index: 0, error: TxError::InvalidSignature }));
+    assert!(append_block_with_tx(bc, genesis).is_ok());
+
+    let mut tx_transfer = Transaction::new(
+        TransactionData::Transfer { to: satoshi_id.clone(), amount: 1 }, Some(satoshi_id.clone()));
+    tx_transfer.sign(&satoshi_keypair, other.chain_id());
+    assert_eq!(append_block_with_tx(bc, vec![tx_transfer.clone()]), Err(BlockError::Transaction { index: 1, error: TxError::InvalidSignature }));
+
+    tx_transfer.sign(&satoshi_keypair, bc.chain_id());
+    assert!(append_block_with_tx(bc, vec![tx_transfer]).is_ok());
+    assert!(bc.validate_full().is_ok());
+}
+
+#[test]
+fn test_chain_id_from_genesis() {
+    let bc = &mut Blockchain::new();
+    assert_eq!(bc.chain_id(), 0);
+
+    let block = create_block(bc, "satoshi".to_string());
+    let genesis_hash = block.hash.clone().unwrap();
+    assert!(bc.append_block(block).is_ok());
+    // the trailing bytes, as the proof-of-work zeroes the leading ones
+    assert_eq!(format!("{:016x}", bc.chain_id()), genesis_hash[48..]);
+
+    let block = create_block(bc, "alice".to_string());
+    assert!(bc.append_block(block).is_ok());
+    assert!(bc.validate_full().is_ok());
+}
+
+#[test]
+fn test_encoding_round_trip() {
+    let bc = &mut Blockchain::new();
+    let block = create_block_and_tx(bc, vec![10, 0], 5, "bob".to_string(), "alice".to_string());
+    assert!(bc.append_block(block.clone()).is_ok());
+
+    let bytes = block.encode();
+    let decoded = Block::decode(&bytes).unwrap();
+    assert_eq!(decoded, block);
+    assert!(decoded.verify());
+    assert_eq!(decoded.encode(), bytes);
+
+    for tx in &block.transactions {
+        assert_eq!(Transaction::decode(&tx.encode()).as_ref(), Ok(tx));
+    }
+
+    let account = bc.get_account_by_id("bob").unwrap().clone();
+    assert_eq!(Account::decode(&account.encode()), Ok(account));
+
+    // the signature isn't hashed, everything else is
+    let mut tx = block.transactions[4].clone();
+    tx.signature = None;
+    assert_eq!(tx.hash(), block.transactions[4].hash());
+    tx.nonce += 1;
+    assert_ne!(tx.hash(), block.transactions[4].hash());
+
+    assert!(Block::decode(&bytes[..bytes.len() - 1]).is_err());
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert!(Block::decode(&trailing).is_err());
+}
+
+#[test]
+fn test_mempool() {
+    let bc = &mut Blockchain::new();
+    let satoshi_keypair = generate_keypair();
+    let satoshi_id = "satoshi".to_string();
+    let alice_keypair = generate_keypair();
+    let alice_id = "alice".to_string();
+    assert!(append_genesis(bc, &[(satoshi_id.clone(), &satoshi_keypair, 1000), (alice_id.clone(), &alice_keypair, 0)]).is_ok());
+
+    let tx0 = transfer_tx(bc, &satoshi_id, &satoshi_keypair, &alice_id, 100, 0, 0);
+    let tx1 = transfer_tx(bc, &satoshi_id, &satoshi_keypair, &alice_id, 100, 0, 1);
+    assert!(bc.submit_transaction(tx1.clone()).is_ok());
+    assert!(bc.pending_transactions().is_empty());
+    assert!(bc.submit_transaction(tx0.clone()).is_ok());
+    assert!(bc.submit_transaction(tx0.clone()).is_err());
+
+    // rejected on admission
+    let mut other_chain = tx0.clone();
+    other_chain.nonce = 2;
+    other_chain.sign(&satoshi_keypair, bc.chain_id() + 1);
+    assert_eq!(bc.submit_transaction(other_chain), Err(MempoolError::Invalid(TxError::InvalidSignature)));
+    assert!(bc.submit_transaction(transfer_tx(bc, &alice_id, &alice_keypair, &satoshi_id, 1, 0, 0)).is_err());
+    assert!(bc.submit_transaction(Transaction::new(
+        TransactionData::MintInitialSupply { to: alice_id.clone(), amount: 1 }, None)).is_err());
+
+    let bob_keypair = generate_keypair();
+    let mut tx_create_bob = Transaction::new(
+        TransactionData::CreateAccount("bob".to_string(), bob_keypair.public), Some("bob".to_string()));
+    tx_create_bob.sign(&bob_keypair, bc.chain_id());
+    assert!(bc.submit_transaction(tx_create_bob.clone()).is_ok());
+
+    let pending: Vec<Transaction> = bc.pending_transactions().into_iter().cloned().collect();
+    assert_eq!(pending.iter().map(|tx| tx.hash()).collect::<Vec<_>>(), vec![tx0.hash(), tx1.hash(), tx_create_bob.hash()]);
+
+    // included transactions leave the pool
+    assert!(append_block_with_tx(bc, pending[..2].to_vec()).is_ok());
+    assert_eq!(bc.mempool_len(), 1);
+    assert!(append_block_with_tx(bc, vec![tx_create_bob]).is_ok());
+    assert_eq!(bc.mempool_len(), 0);
+    assert_eq!(bc.submit_transaction(tx0), Err(MempoolError::Invalid(TxError::InvalidNonce { nonce: 0, expected: 2 })));
+}
+
+#[test]
+fn test_build_block() {
+    let bc = &mut Blockchain::new();
+    let satoshi_keypair = generate_keypair();
+    let satoshi_id = "satoshi".to_string();
+    let alice_keypair = generate_keypair();
+    let alice_id = "alice".to_string();
+    assert!(append_genesis(bc, &[(satoshi_id.clone(), &satoshi_keypair, 1000), (alice_id.clone(), &alice_keypair, 0)]).is_ok());
+    assert_eq!(bc.build_block(&satoshi_id).unwrap().transactions.len(), 1);
+    assert!(bc.build_block(&"nobody".to_string()).is_err());
+
+    let tx0 = transfer_tx(bc, &satoshi_id, &satoshi_keypair, &alice_id, 600, 0, 0);
+    let tx_create_bob = {
+        let bob_keypair = generate_keypair();
+        let mut tx = Transaction::new(
+            TransactionData::CreateAccount("bob".to_string(), bob_keypair.public), Some("bob".to_string()));
+        tx.sign(&bob_keypair, bc.chain_id());
+        tx
+    };
+    let transactions = vec![
+        tx0.clone(),
+        // each one is admitted, but satoshi can't pay for both
+        transfer_tx(bc, &satoshi_id, &satoshi_keypair, &alice_id, 600, 0, 1),
+        transfer_tx(bc, &satoshi_id, &satoshi_keypair, &alice_id, 100, 0, 2),
+        transfer_tx(bc, &alice_id, &alice_keypair, &"nobody".to_string(), 0, 0, 0),
+        tx_create_bob.clone(),
+    ];
+    for tx in transactions {
+        assert!(bc.submit_transaction(tx).is_ok());
+    }
+
+    let mut block = bc.build_block(&alice_id).unwrap();
+    assert_eq!(block.header.prev_hash, bc.get_last_block_hash());
+    assert_eq!(block.header.height, 1);
+    assert!(block.transactions[0].is_coinbase());
+    assert_eq!(block.transactions[1..].iter().map(|tx| tx.hash()).collect::<Vec<_>>(), vec![tx0.hash(), tx_create_bob.hash()]);
+
+    let report = Miner::new(1).mine(&mut block, bc.get_current_target());
+    assert!(matches!(report.result, MiningResult::Found { .. }));
+    assert!(bc.append_block(block).is_ok());
+    assert_eq!(bc.get_account_by_id(&alice_id).unwrap().balance, 600 + INITIAL_SUBSIDY);
+    assert_eq!(bc.mempool_len(), 3);
+}
+
+#[test]
+fn test_detached_transactions_return_to_mempool() {
+    let bc = &mut Blockchain::new();
+    let satoshi_keypair = generate_keypair();
+    let satoshi_id = "satoshi".to_string();
+    let alice_id = "alice".to_string();
+    assert!(append_genesis(bc, &[(satoshi_id.clone(), &satoshi_keypair, 1000), (alice_id.clone(), &generate_keypair(), 0)]).is_ok());
+    let genesis_hash = bc.get_last_block_hash();
+
+    let transfer = transfer_tx(bc, &satoshi_id, &satoshi_keypair, &alice_id, 100, 0, 0);
+    assert!(append_block_with_tx(bc, vec![transfer.clone()]).is_ok());
+    assert_eq!(bc.mempool_len(), 0);
+
+    // a heavier branch without the transfer: it goes back to the pool, the coinbase doesn't
+    let block_b1 = create_block_on(bc, genesis_hash, "bob".to_string());
+    assert!(bc.append_block(block_b1.clone()).is_ok());
+    let block_b2 = create_block_on(bc, block_b1.hash.clone(), "carol".to_string());
+    assert!(bc.append_block(block_b2).is_ok());
+    assert_eq!(bc.get_account_by_id(&alice_id).unwrap().balance, 0);
+    assert_eq!(bc.pending_transactions(), vec![&transfer]);
+
+    let mut block = bc.build_block(&satoshi_id).unwrap();
+    assert_eq!(block.transactions.len(), 2);
+    assert_eq!(block.transactions[1], transfer);
+    assert!(mining(&mut block, bc).is_ok());
+    assert!(bc.append_block(block).is_ok());
+    assert_eq!(bc.mempool_len(), 0);
+
+    // popped blocks too
+    assert!(bc.pop_block().is_some());
+    assert_eq!(bc.pending_transactions(), vec![&transfer]);
+    // carol's account creation joins it
+    assert_eq!(bc.rewind_to(1).len(), 1);
+    assert_eq!(bc.mempool_len(), 2);
+}
+
+#[test]
+fn test_coinbase() {
+    let bc = &mut Blockchain::new().with_reward_schedule(RewardSchedule::new(100, 2));
+    let satoshi_keypair = generate_keypair();
+    let satoshi_id = "satoshi".to_string();
+    assert!(append_genesis(bc, &[(satoshi_id.clone(), &satoshi_keypair, 0)]).is_ok());
+
+    let coinbase = |amount, height| Transaction::new(
+        TransactionData::Coinbase { to: "satoshi".to_string(), amount, height }, None);
+
+    assert_eq!(
+        append_block_with_tx(bc, vec![coinbase(101, 1)]),
+        Err(BlockError::Transaction { index: 0, error: TxError::ExcessiveCoinbase { amount: 101, subsidy: 100 } })
+    );
+    assert_eq!(
+        append_block_with_tx(bc, vec![coinbase(100, 2)]),
+        Err(BlockError::Transaction { index: 0, error: TxError::InvalidCoinbaseHeight { height: 2, expected: 1 } })
+    );
+    assert!(append_block_with_tx(bc, vec![coinbase(100, 1)]).is_ok());
+
+    // halved at height 2, claiming less is fine
+    assert!(append_block_with_tx(bc, vec![coinbase(51, 2)]).is_err());
+    assert!(append_block_with_tx(bc, vec![coinbase(20, 2)]).is_ok());
+    assert_eq!(bc.get_account_by_id(&satoshi_id).unwrap().balance, 120);
+
+    // a block has to start with its coinbase, and can't have another one
+    let transfer = transfer_tx(bc, &satoshi_id, &satoshi_keypair, &satoshi_id, 1, 0, 0);
+    let mut block = Block::new(bc.get_last_block_hash());
+    block.add_transaction(transfer.clone());
+    assert!(mining(&mut block, bc).is_ok());
+    assert_eq!(bc.append_block(block), Err(BlockError::MissingCoinbase));
+    assert_eq!(
+        append_block_with_tx(bc, vec![coinbase(1, 3), transfer.clone(), coinbase(1, 3)]),
+        Err(BlockError::MisplacedCoinbase { index: 2 })
+    );
+    assert!(append_block_with_tx(bc, vec![coinbase(1, 3), coinbase(1, 3)]).is_err());
+    assert!(bc.submit_transaction(coinbase(1, 3)).is_err());
+    assert!(append_block_with_tx(bc, vec![coinbase(50, 3), transfer]).is_ok());
+    assert!(bc.validate_full().is_ok());
+}
+
+#[test]
+fn test_fees() {
+    let bc = &mut Blockchain::new();
+    let satoshi_keypair = generate_keypair();
+    let satoshi_id = "satoshi".to_string();
+    let alice_keypair = generate_keypair();
+    let alice_id = "alice".to_string();
+    let miner_keypair = generate_keypair();
+    let miner_id = "miner".to_string();
+    assert!(append_genesis(bc, &[
+        (satoshi_id.clone(), &satoshi_keypair, 1000),
+        (alice_id.clone(), &alice_keypair, 0),
+        (miner_id.clone(), &miner_keypair, 0),
+    ]).is_ok());
+    let balance = |bc: &Blockchain, id: &String| bc.get_account_by_id(id).unwrap().balance;
+    let coinbase = |height| Transaction::new(
+        TransactionData::Coinbase { to: "miner".to_string(), amount: 0, height }, None);
+
+    // the fee goes to the account the coinbase pays
+    let transfer = transfer_tx(bc, &satoshi_id, &satoshi_keypair, &alice_id, 100, 10, 0);
+    assert!(append_block_with_tx(bc, vec![coinbase(1), transfer]).is_ok());
+    assert_eq!((balance(bc, &satoshi_id), balance(bc, &alice_id), balance(bc, &miner_id)), (890, 100, 10));
+
+    // the balance has to cover the amount and the fee
+    let transfer = transfer_tx(bc, &satoshi_id, &satoshi_keypair, &alice_id, 890, 1, 1);
+    assert_eq!(append_block_with_tx(bc, vec![transfer]), Err(BlockError::Transaction { index: 1, error: TxError::InsufficientBalance }));
+
+    // the fee is signed
+    let mut transfer = transfer_tx(bc, &satoshi_id, &satoshi_keypair, &alice_id, 10, 1, 1);
+    transfer.fee = 0;
+    assert_eq!(append_block_with_tx(bc, vec![transfer]), Err(BlockError::Transaction { index: 1, error: TxError::InvalidSignature }));
+
+    // a coinbase claiming nothing still collects the fees
+    let transfer = transfer_tx(bc, &satoshi_id, &satoshi_keypair, &alice_id, 10, 5, 1);
+    assert!(append_block_with_tx(bc, vec![coinbase(2), transfer]).is_ok());
+    assert_eq!((balance(bc, &satoshi_id), balance(bc, &alice_id), balance(bc, &miner_id)), (875, 110, 15));
+
+    // creating an account costs a fee too, paid by the sender who signs it
+    let bob_keypair = generate_keypair();
+    let mut tx_create_bob = Transaction::new(
+        TransactionData::CreateAccount("bob".to_string(), bob_keypair.public), Some(alice_id.clone()));
+    tx_create_bob.fee = 3;
+    tx_create_bob.sign(&bob_keypair, bc.chain_id());
+    assert_eq!(
+        append_block_with_tx(bc, vec![coinbase(3), tx_create_bob.clone()]),
+        Err(BlockError::Transaction { index: 1, error: TxError::InvalidSignature })
+    );
+    tx_create_bob.sign(&alice_keypair, bc.chain_id());
+    assert!(append_block_with_tx(bc, vec![coinbase(3), tx_create_bob]).is_ok());
+    assert_eq!((balance(bc, &alice_id), balance(bc, &miner_id)), (107, 18));
+
+    let mut mint = Transaction::new(TransactionData::MintInitialSupply { to: alice_id.clone(), amount: 1 }, None);
+    mint.fee = 1;
+    assert_eq!(append_block_with_tx(bc, vec![mint]), Err(BlockError::Transaction { index: 1, error: TxError::UnexpectedFee }));
+    assert!(bc.validate_full().is_ok());
+}
+
+#[test]
+fn test_base_fee() {
+    let satoshi_keypair = generate_keypair();
+    let satoshi_id = "satoshi".to_string();
+    let alice_keypair = generate_keypair();
+    let alice_id = "alice".to_string();
+    let miner_keypair = generate_keypair();
+    let miner_id = "miner".to_string();
+    // transfers all take the same space, the target is one of them
+    let transfer_size = transfer_tx(&Blockchain::new(), &satoshi_id, &satoshi_keypair, &alice_id, 10, 0, 0).encode().len();
+    let bc = &mut Blockchain::new().with_fee_market(FeeMarket::new(transfer_size, 8));
+    assert!(append_genesis(bc, &[
+        (satoshi_id.clone(), &satoshi_keypair, 1000),
+        (alice_id.clone(), &alice_keypair, 0),
+        (miner_id.clone(), &miner_keypair, 0),
+    ]).is_ok());
+    let balance = |bc: &Blockchain, id: &String| bc.get_account_by_id(id).unwrap().balance;
+    let coinbase = |height| Transaction::new(
+        TransactionData::Coinbase { to: "miner".to_string(), amount: 0, height }, None);
+
+    // mints and accounts created by themselves pay no base fee, so they don't fill the block
+    assert_eq!(bc.get_next_base_fee(), 0);
+    let transfers = vec![
+        transfer_tx(bc, &satoshi_id, &satoshi_keypair, &alice_id, 10, 0, 0),
+        transfer_tx(bc, &satoshi_id, &satoshi_keypair, &alice_id, 10, 0, 1),
+    ];
+    assert!(append_block_with_tx(bc, transfers).is_ok());
+
+    // above the target, a zero base fee rises
+    assert_eq!(bc.get_next_base_fee(), 1);
+    let transfer = transfer_tx(bc, &satoshi_id, &satoshi_keypair, &alice_id, 10, 0, 2);
+    assert!(bc.submit_transaction(transfer.clone()).is_err());
+    assert_eq!(
+        append_block_with_tx(bc, vec![transfer]),
+        Err(BlockError::Transaction { index: 1, error: TxError::FeeBelowBaseFee { fee: 0, base_fee: 1 } })
+    );
+
+    // the base fee is burned, the rest goes to the miner
+    let bob_keypair = generate_keypair();
+    let mut tx_create_bob = Transaction::new(
+        TransactionData::CreateAccount("bob".to_string(), bob_keypair.public), Some("bob".to_string()));
+    tx_create_bob.sign(&bob_keypair, bc.chain_id());
+    let transfer = transfer_tx(bc, &satoshi_id, &satoshi_keypair, &alice_id, 10, 3, 2);
+    assert!(append_block_with_tx(bc, vec![coinbase(2), transfer, tx_create_bob]).is_ok());
+    assert_eq!((balance(bc, &satoshi_id), balance(bc, &alice_id), balance(bc, &miner_id)), (967, 30, 2));
+    assert_eq!(bc.total_supply(), 999);
+
+    // on target, the base fee stays
+    assert_eq!(bc.get_next_base_fee(), 1);
+    let transfers = vec![
+        transfer_tx(bc, &satoshi_id, &satoshi_keypair, &alice_id, 10, 1, 3),
+        transfer_tx(bc, &satoshi_id, &satoshi_keypair, &alice_id, 10, 1, 4),
+    ];
+    assert!(append_block_with_tx(bc, transfers).is_ok());
+    assert_eq!(bc.get_next_base_fee(), 2);
+
+    // the block records the base fee it was built with
+    let mut block = bc.build_block(&miner_id).unwrap();
+    assert_eq!(block.header.base_fee, 2);
+    block.header.base_fee = 1;
+    assert!(matches!(Miner::new(1).mine(&mut block, bc.get_current_target()).result, MiningResult::Found { .. }));
+    assert_eq!(bc.append_block(block), Err(BlockError::InvalidBaseFee { base_fee: 1, expected: 2 }));
+    assert!(bc.validate_full().is_ok());
+}
+
+#[test]
+fn test_block_limits() {
+    let satoshi_keypair = generate_keypair();
+    let satoshi_id = "satoshi".to_string();
+    let coinbase = |height| Transaction::new(
+        TransactionData::Coinbase { to: "satoshi".to_string(), amount: 0, height }, None);
+    let setup = |limits| {
+        let mut bc = Blockchain::new().with_block_limits(limits);
+        assert!(append_genesis(&mut bc, &[(satoshi_id.clone(), &satoshi_keypair, 1000)]).is_ok());
+        let transfers: Vec<Transaction> = (0..3)
+            .map(|nonce| transfer_tx(&bc, &satoshi_id, &satoshi_keypair, &satoshi_id, 1, 0, nonce))
+            .collect();
+        for tx in &transfers {
+            assert!(bc.submit_transaction(tx.clone()).is_ok());
+        }
+        (bc, transfers)
+    };
+
+    // transaction count, the coinbase included
+    let (mut bc, transfers) = setup(BlockLimits::new(1_000_000, 3));
+    assert_eq!(bc.build_block(&satoshi_id).unwrap().transactions.len(), 3);
+    let mut block = vec![coinbase(1)];
+    block.extend(transfers.clone());
+    assert_eq!(append_block_with_tx(&mut bc, block), Err(BlockError::TooManyTransactions { count: 4, limit: 3 }));
+    assert!(append_block_with_tx(&mut bc, vec![coinbase(1), transfers[0].clone(), transfers[1].clone()]).is_ok());
+
+    // encoded size, one byte short of two transfers
+    let max_size = HEADER_SIZE + 4 + coinbase(1).encode().len() + 2 * transfers[0].encode().len() - 1;
+    let (mut bc, transfers) = setup(BlockLimits::new(max_size, 100));
+    assert_eq!(bc.build_block(&satoshi_id).unwrap().transactions.len(), 2);
+    let block = vec![coinbase(1), transfers[0].clone(), transfers[1].clone()];
+    assert_eq!(append_block_with_tx(&mut bc, block), Err(BlockError::TooLarge { size: max_size + 1, limit: max_size }));
+    assert!(append_block_with_tx(&mut bc, vec![coinbase(1), transfers[0].clone()]).is_ok());
+}
+
+#[test]
+fn test_supply() {
+    let satoshi_keypair = generate_keypair();
+    let satoshi_id = "satoshi".to_string();
+    let mint = |amount| Transaction::new(
+        TransactionData::MintInitialSupply { to: "satoshi".to_string(), amount }, None);
+    let coinbase = |amount, height| Transaction::new(
+        TransactionData::Coinbase { to: "satoshi".to_string(), amount, height }, None);
+
+    // an overflowing balance rolls the whole block back
+    let bc = &mut Blockchain::new();
+    let mut genesis = Vec::new();
+    let mut tx_create_satoshi = Transaction::new(
+        TransactionData::CreateAccount(satoshi_id.clone(), satoshi_keypair.public), Some(satoshi_id.clone()));
+    tx_create_satoshi.sign(&satoshi_keypair, bc.chain_id());
+    genesis.push(tx_create_satoshi);
+    genesis.push(mint(Balance::MAX));
+    genesis.push(mint(1));
+    assert_eq!(
+        append_block_with_tx(bc, genesis),
+        Err(BlockError::Transaction { index: 2, error: TxError::State(StateError::BalanceOverflow(satoshi_id.clone())) })
+    );
+    assert!(bc.get_account_by_id(&satoshi_id).is_none());
+    assert_eq!(bc.total_supply(), 0);
+
+    let bc = &mut Blockchain::new()
+        .with_reward_schedule(RewardSchedule::new(100, 1_000))
+        .with_supply_cap(1_000);
+    assert!(append_genesis(bc, &[(satoshi_id.clone(), &satoshi_keypair, 950)]).is_ok());
+    assert_eq!(bc.total_supply(), 950);
+
+    // a coinbase can't claim more than what is left under the cap
+    assert_eq!(
+        append_block_with_tx(bc, vec![coinbase(100, 1)]),
+        Err(BlockError::Transaction {
+            index: 0,
+            error: TxError::State(StateError::SupplyCapExceeded { supply: 1_050, cap: 1_000 }),
+        })
+    );
+    let mut block = bc.build_block(&satoshi_id).unwrap();
+    assert!(matches!(Miner::new(1).mine(&mut block, bc.get_current_target()).result, MiningResult::Found { .. }));
+    assert!(bc.append_block(block).is_ok());
+    assert_eq!(bc.total_supply(), 1_000);
+    assert_eq!(bc.get_account_by_id(&satoshi_id).unwrap().balance, 1_000);
+
+    // fees above the base fee go to the miner
+    let transfer = transfer_tx(bc, &satoshi_id, &satoshi_keypair, &satoshi_id, 10, 7, 0);
+    assert!(append_block_with_tx(bc, vec![coinbase(0, 2), transfer]).is_ok());
+    assert_eq!(bc.total_supply(), 1_000);
+    assert!(bc.validate_full().is_ok());
+}
+
+#[test]
+fn test_issuance_pays_no_fee() {
+    let satoshi_keypair = generate_keypair();
+    let satoshi_id = "satoshi".to_string();
+    let bc = &mut Blockchain::new().with_supply_cap(1_000);
+
+    // a fee nobody paid can't be burned to make room under the cap
+    let mut tx_create_satoshi = Transaction::new(
+        TransactionData::CreateAccount(satoshi_id.clone(), satoshi_keypair.public), Some(satoshi_id.clone()));
+    tx_create_satoshi.sign(&satoshi_keypair, bc.chain_id());
+    let mut mint = Transaction::new(
+        TransactionData::MintInitialSupply { to: satoshi_id.clone(), amount: 1_000 }, Some(satoshi_id.clone()));
+    mint.fee = 1_000;
+    let genesis = vec![tx_create_satoshi.clone(), mint.clone(), mint.clone()];
+    assert_eq!(
+        append_block_with_tx(bc, genesis),
+        Err(BlockError::Transaction { index: 1, error: TxError::UnexpectedSender })
+    );
+    mint.fee = 0;
+    assert_eq!(
+        append_block_with_tx(bc, vec![tx_create_satoshi, mint]),
+        Err(BlockError::Transaction { index: 1, error: TxError::UnexpectedSender })
+    );
+    assert_eq!(bc.total_supply(), 0);
+
+    assert!(append_genesis(bc, &[(satoshi_id.clone(), &satoshi_keypair, 1_000)]).is_ok());
+    let coinbase = Transaction::new(
+        TransactionData::Coinbase { to: satoshi_id.clone(), amount: 0, height: 1 }, Some(satoshi_id.clone()));
+    assert_eq!(
+        append_block_with_tx(bc, vec![coinbase]),
+        Err(BlockError::Transaction { index: 0, error: TxError::UnexpectedSender })
+    );
+    let mut coinbase = Transaction::new(
+        TransactionData::Coinbase { to: satoshi_id.clone(), amount: 0, height: 1 }, None);
+    coinbase.fee = 1;
+    assert_eq!(
+        append_block_with_tx(bc, vec![coinbase]),
+        Err(BlockError::Transaction { index: 0, error: TxError::UnexpectedFee })
+    );
+    assert_eq!(bc.total_supply(), 1_000);
+}
+
+#[test]
+fn test_pop_block() {
+    let bc = &mut Blockchain::new();
+    let satoshi_keypair = generate_keypair();
+    let satoshi_id = "satoshi".to_string();
+    let alice_keypair = generate_keypair();
+    let alice_id = "alice".to_string();
+    assert!(append_genesis(bc, &[(satoshi_id.clone(), &satoshi_keypair, 1000)]).is_ok());
+    let accounts = |bc: &Blockchain| vec![
+        bc.get_account_by_id(&satoshi_id).cloned(),
+        bc.get_account_by_id(&alice_id).cloned(),
+    ];
+    let genesis_state = accounts(bc);
+
+    let mut tx_create_alice = Transaction::new(
+        TransactionData::CreateAccount(alice_id.clone(), alice_keypair.public), Some(satoshi_id.clone()));
+    tx_create_alice.fee = 5;
+    tx_create_alice.sign(&satoshi_keypair, bc.chain_id());
+    assert!(append_block_with_tx(bc, vec![tx_create_alice]).is_ok());
+    let created_state = accounts(bc);
+
+    let coinbase = Transaction::new(
+        TransactionData::Coinbase { to: alice_id.clone(), amount: INITIAL_SUBSIDY, height: 2 }, None);
+    let transfer = transfer_tx(bc, &satoshi_id, &satoshi_keypair, &alice_id, 100, 3, 1);
+    assert!(append_block_with_tx(bc, vec![coinbase, transfer]).is_ok());
+    let head = bc.blocks.head().unwrap().clone();
+    let supply = bc.total_supply();
+    assert_eq!(supply, 1000 + INITIAL_SUBSIDY);
+
+    // a side branch stays known until the block it forks from is popped
+    let side = create_block_on(bc, head.header.prev_hash.clone(), "bob".to_string());
+    assert!(bc.append_block(side.clone()).is_ok());
+    assert_eq!(bc.side_blocks_len(), 1);
+
+    assert_eq!(bc.pop_block(), Some(head.clone()));
+    assert_eq!(accounts(bc), created_state);
+    assert_eq!(bc.total_supply(), 1000);
+    assert_eq!(bc.len(), 2);
+    assert_eq!(bc.side_blocks_len(), 1);
+
+    // popped blocks can be appended again
+    assert!(bc.append_block(head.clone()).is_ok());
+    assert_eq!(bc.total_supply(), supply);
+    assert!(bc.validate_full().is_ok());
+
+    let popped = bc.rewind_to(0);
+    assert_eq!(popped.len(), 2);
+    assert_eq!(popped[0], head);
+    assert_eq!(accounts(bc), genesis_state);
+    assert_eq!(bc.total_supply(), 1000);
+    assert_eq!(bc.side_blocks_len(), 0);
+    assert!(bc.get_block_by_hash(&side.hash.clone().unwrap()).is_none());
+    assert!(bc.validate_full().is_ok());
+
+    let chain_id = bc.chain_id();
+    assert!(bc.pop_block().is_some());
+    assert!(bc.is_empty());
+    assert!(bc.get_account_by_id(&satoshi_id).is_none());
+    assert_eq!(bc.chain_id(), 0);
+    assert_ne!(chain_id, 0);
+    assert_eq!(bc.pop_block(), None);
+}
+
+#[test]
+fn test_state_backends() {
+    let alice_keypair = generate_keypair();
+    let bob_keypair = generate_keypair();
+    let alice_id = "alice".to_string();
+    let bob_id = "bob".to_string();
+    let bc = &mut Blockchain::new();
+    assert!(append_genesis(bc, &[(alice_id.clone(), &alice_keypair, 100), (bob_id.clone(), &bob_keypair, 0)]).is_ok());
+    assert!(bc.contains_account("alice"));
+    assert_eq!(bc.accounts().count(), 2);
+
+    // fails after the transfer itself, paying the fee to a miner without an account
+    let tx = transfer_tx(bc, &alice_id, &alice_keypair, &bob_id, 10, 1, 0);
+    let context = ExecutionContext {
+        chain_id: bc.chain_id(),
+        height: 1,
+        miner: Some("carol".to_string()),
+        ..Default::default()
+    };
+    let unknown_miner = Err(TxError::UnknownAccount("carol".to_string()));
+
+    // every backend reverts the failed transaction
+    let mut overlay = StateOverlay::new(&*bc);
+    assert_eq!(tx.execute(&mut overlay, &context), unknown_miner);
+    let alice = overlay.get_account_by_id("alice").unwrap();
+    assert_eq!((alice.balance, alice.nonce), (100, 0));
+    assert_eq!(overlay.get_account_by_id("bob").unwrap().balance, 0);
+
+    let mut accounts: MemoryState = bc.accounts()
+        .map(|(account_id, account)| (account_id.clone(), account.clone()))
+        .collect();
+    assert_eq!(tx.execute(&mut accounts, &context), unknown_miner);
+    assert_eq!(accounts.get_account_by_id("alice").unwrap().balance, 100);
+    assert_eq!(accounts.get_account_by_id("bob").unwrap().balance, 0);
+
+    assert_eq!(tx.execute(bc, &context), unknown_miner);
+    assert_eq!(bc.get_account_by_id("alice").unwrap().balance, 100);
+    assert_eq!(bc.get_next_nonce("alice"), Some(0));
+
+    assert_eq!(accounts.remove_account("alice").map(|account| account.balance), Some(100));
+    assert!(!accounts.contains_account("alice"));
+    assert_eq!(accounts.get_next_nonce("alice"), None);
+    assert!(bc.contains_account("alice"));
+}